@@ -0,0 +1,304 @@
+pub mod image;
+pub mod logos;
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{BuildHasher, RandomState},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, de::IntoDeserializer};
+
+use crate::{
+    config::{
+        ArtAlign, ArtPick, ArtPosition, command,
+        condition::matches,
+        style::{Color, Style, reset},
+        system, timeout,
+    },
+    width::display_width,
+};
+
+/// Replaces the `${name}` placeholders in `art` with the matching style from
+/// `colors`. Names that aren't in `colors` can be a color, which is used as the
+/// foreground, or `reset`. A `$${` is left as a literal `${`.
+///
+/// A style lasts until the next placeholder, even across lines, but is reset at
+/// the end of every line so it doesn't leak into the padding next to the art.
+pub fn render(art: &str, colors: &HashMap<String, Style>) -> io::Result<String> {
+    expand(art, colors, true)
+}
+
+/// Like [`render`], but for the output of `art_command`, which wasn't written
+/// with placeholders in mind. Any `${` that isn't a known placeholder is left
+/// as it is instead of being an error.
+pub fn render_output(output: &str, colors: &HashMap<String, Style>) -> io::Result<String> {
+    expand(output, colors, false)
+}
+
+fn expand(art: &str, colors: &HashMap<String, Style>, strict: bool) -> io::Result<String> {
+    let mut out = String::new();
+    let mut current: Option<Style> = None;
+    for line in art.lines() {
+        if let Some(style) = current {
+            out.push_str(&style.format_start());
+        }
+        let mut rest = line;
+        while let Some(start) = rest.find("${") {
+            if rest[..start].ends_with('$') {
+                out.push_str(&rest[..start - 1]);
+                out.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            out.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find('}') else {
+                if !strict {
+                    rest = &rest[start..];
+                    break;
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unclosed `${{` in art line `{line}`."),
+                ));
+            };
+            let name = &rest[start + 2..start + end];
+            let style = match placeholder(name, colors) {
+                Ok(style) => style,
+                Err(_) if !strict => {
+                    out.push_str(&rest[start..=start + end]);
+                    rest = &rest[start + end + 1..];
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if current.is_some() {
+                out.push_str(reset());
+            }
+            current = style;
+            if let Some(style) = current {
+                out.push_str(&style.format_start());
+            }
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+        if current.is_some() {
+            out.push_str(reset());
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Picks the art file to use. If `path` is a directory, one of the files in it
+/// is chosen as `pick` says, and if its file name is a glob with `*` or `?`,
+/// one of the files it matches is. Any other path is used as it is.
+pub fn pick(path: &Path, pick: ArtPick) -> io::Result<PathBuf> {
+    let (dir, pattern) = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.contains(['*', '?']) => (path.parent().unwrap_or(Path::new(".")), name),
+        _ if path.is_dir() => (path, "*"),
+        _ => return Ok(path.to_path_buf()),
+    };
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if !name.starts_with('.') && matches(pattern, name) && entry.path().is_file() {
+            files.push(entry.path());
+        }
+    }
+    if files.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("There is no art matching `{}`.", path.display()),
+        ));
+    }
+    // Sorted, so that `daily` goes through the files in order.
+    files.sort();
+
+    let n = match pick {
+        ArtPick::Random => RandomState::new().hash_one(0),
+        ArtPick::Daily => {
+            let since_epoch = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            since_epoch.as_secs() / (24 * 60 * 60)
+        }
+        ArtPick::ByHost => fnv1a(system::hostname()?.as_bytes()),
+    };
+    Ok(files.swap_remove((n % files.len() as u64) as usize))
+}
+
+/// A hash that, unlike the one in `std`, is the same in every build.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Runs `art_command` with `sh` and returns what it printed, so it can be used
+/// as art. Like other commands, it is stopped after `timeout_ms`.
+pub fn run(art_command: &str, timeout_ms: Option<u64>) -> io::Result<String> {
+    let output = command::output(
+        Command::new("sh").args(["-c", art_command]),
+        timeout(timeout_ms),
+    )?
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("The art command `{art_command}` timed out."),
+        )
+    })?;
+    String::from_utf8(output.stdout).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid UTF8 was outputted by the art command `{art_command}`."),
+        )
+    })
+}
+
+fn placeholder(name: &str, colors: &HashMap<String, Style>) -> io::Result<Option<Style>> {
+    if let Some(style) = colors.get(name) {
+        return Ok(Some(*style));
+    }
+    if name == "reset" {
+        return Ok(None);
+    }
+    let color =
+        Color::deserialize(name.into_deserializer()).map_err(|_: serde::de::value::Error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown art placeholder `${{{name}}}`. Define it in `[art.colors]`."),
+            )
+        })?;
+    Ok(Some(Style {
+        fg: color,
+        ..Default::default()
+    }))
+}
+
+/// Where to put the art in a terminal `columns` wide. Art on the left or right
+/// moves to the top when it doesn't fit next to `fetch`, and is left out when
+/// it doesn't fit at all. `top_below` and `hide_below` change the widths at
+/// which that happens.
+pub fn fit(
+    position: ArtPosition,
+    art: &str,
+    fetch: &str,
+    columns: usize,
+    top_below: Option<usize>,
+    hide_below: Option<usize>,
+) -> ArtPosition {
+    let art_width = art.lines().map(display_width).max().unwrap_or(0);
+    let fetch_width = fetch.lines().map(display_width).max().unwrap_or(0);
+    if columns < hide_below.unwrap_or(art_width) {
+        return ArtPosition::None;
+    }
+    match position {
+        ArtPosition::Left | ArtPosition::Right
+            if columns < top_below.unwrap_or(art_width + 1 + fetch_width) =>
+        {
+            ArtPosition::Top
+        }
+        position => position,
+    }
+}
+
+/// Prints `art` and the rendered `fetch` box next to each other as `position`
+/// says. `align` decides where the shorter block sits next to the taller one
+/// when they are side by side.
+///
+/// An `overlay`, like an image drawn with escape codes, is drawn over the top
+/// left corner of the art once everything else is printed.
+pub fn compose(
+    art: &str,
+    overlay: Option<&str>,
+    fetch: &str,
+    position: ArtPosition,
+    align: ArtAlign,
+    mut out: impl Write,
+) -> io::Result<()> {
+    // Where the art starts, and how many lines were printed in total.
+    let (art_row, art_col, height) = match position {
+        ArtPosition::None => {
+            return out.write_all(fetch.as_bytes());
+        }
+        ArtPosition::Top => {
+            for line in art.lines() {
+                writeln!(out, "{line}")?;
+            }
+            out.write_all(fetch.as_bytes())?;
+            (0, 0, art.lines().count() + fetch.lines().count())
+        }
+        ArtPosition::Bottom => {
+            out.write_all(fetch.as_bytes())?;
+            for line in art.lines() {
+                writeln!(out, "{line}")?;
+            }
+            (
+                fetch.lines().count(),
+                0,
+                art.lines().count() + fetch.lines().count(),
+            )
+        }
+        ArtPosition::Left | ArtPosition::Right => {
+            let art: Vec<_> = art.lines().collect();
+            let fetch: Vec<_> = fetch.lines().collect();
+            let height = art.len().max(fetch.len());
+            let art_offset = align.offset(art.len(), height);
+            let fetch_offset = align.offset(fetch.len(), height);
+            let art_width = art.iter().map(|l| display_width(l)).max().unwrap_or(0);
+            let fetch_width = fetch.iter().map(|l| display_width(l)).max().unwrap_or(0);
+
+            for row in 0..height {
+                let art_line = line_at(&art, art_offset, row);
+                let fetch_line = line_at(&fetch, fetch_offset, row);
+                if let ArtPosition::Left = position {
+                    if fetch_line.is_empty() {
+                        writeln!(out, "{art_line}")?;
+                    } else {
+                        let padding = " ".repeat(art_width - display_width(art_line));
+                        writeln!(out, "{art_line}{padding} {fetch_line}")?;
+                    }
+                } else if art_line.is_empty() {
+                    writeln!(out, "{fetch_line}")?;
+                } else {
+                    let padding = " ".repeat(fetch_width - display_width(fetch_line));
+                    writeln!(out, "{fetch_line}{padding} {art_line}")?;
+                }
+            }
+            match position {
+                ArtPosition::Left => (art_offset, 0, height),
+                _ => (art_offset, fetch_width + 1, height),
+            }
+        }
+    };
+
+    if let Some(overlay) = overlay {
+        // Save the cursor, move to the art, draw, and move back.
+        write!(
+            out,
+            "\x1b7\x1b[{}A\x1b[{}G{overlay}\x1b8",
+            height - art_row,
+            art_col + 1
+        )?;
+    }
+    Ok(())
+}
+
+/// The line at `row` of a block that starts at `offset`, or an empty line if the
+/// block doesn't cover that row.
+fn line_at<'a>(lines: &[&'a str], offset: usize, row: usize) -> &'a str {
+    row.checked_sub(offset)
+        .and_then(|i| lines.get(i))
+        .copied()
+        .unwrap_or("")
+}
@@ -0,0 +1,255 @@
+use std::{fmt::Write, io, path::Path};
+
+use image::{Rgba, RgbaImage, imageops::FilterType};
+use serde::Deserialize;
+
+use crate::{
+    config::style::{Color, Style, color_enabled, reset},
+    terminal,
+};
+
+/// How images are drawn in the terminal.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageProtocol {
+    /// Half-block characters in color, which work in most terminals.
+    #[default]
+    Blocks,
+    Sixel,
+    Kitty,
+}
+
+/// An image rendered as art. `text` takes up the image's space in the layout,
+/// and `overlay`, if there is one, is drawn over that space afterwards.
+pub struct ImageArt {
+    pub text: String,
+    pub overlay: Option<String>,
+}
+
+const DEFAULT_WIDTH: u32 = 32;
+/// The size of a cell in pixels, for terminals that don't report it.
+const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// Loads the image at `path` and renders it `width` columns wide. Without a
+/// `height`, the number of rows follows from the image's aspect ratio.
+pub fn render(
+    path: &Path,
+    width: Option<u16>,
+    height: Option<u16>,
+    protocol: ImageProtocol,
+) -> io::Result<ImageArt> {
+    let image = image::open(path)
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not load the image `{}`: {err}", path.display()),
+            )
+        })?
+        .into_rgba8();
+    let (cell_width, cell_height) = match protocol {
+        ImageProtocol::Blocks => (1, 2),
+        ImageProtocol::Sixel | ImageProtocol::Kitty => cell_size(),
+    };
+    let cols = width.map_or(DEFAULT_WIDTH, u32::from).max(1);
+    let rows = height.map_or_else(
+        || {
+            let pixel_height = cols as u64 * cell_width as u64 * image.height() as u64
+                / image.width().max(1) as u64;
+            pixel_height.div_ceil(cell_height as u64).max(1) as u32
+        },
+        u32::from,
+    );
+    let image = image::imageops::resize(
+        &image,
+        cols * cell_width,
+        rows * cell_height,
+        FilterType::Triangle,
+    );
+
+    if !color_enabled() {
+        return Ok(ImageArt {
+            text: blank(cols, rows),
+            overlay: None,
+        });
+    }
+    Ok(match protocol {
+        ImageProtocol::Blocks => ImageArt {
+            text: blocks(&image),
+            overlay: None,
+        },
+        ImageProtocol::Sixel => ImageArt {
+            text: blank(cols, rows),
+            overlay: Some(sixel(&image)),
+        },
+        ImageProtocol::Kitty => ImageArt {
+            text: blank(cols, rows),
+            overlay: Some(kitty(&image, cols, rows)),
+        },
+    })
+}
+
+fn blank(cols: u32, rows: u32) -> String {
+    (0..rows)
+        .map(|_| " ".repeat(cols as usize) + "\n")
+        .collect()
+}
+
+/// The size of a cell in pixels, as reported by the terminal.
+fn cell_size() -> (u32, u32) {
+    let Some(size) = terminal::size() else {
+        return DEFAULT_CELL_SIZE;
+    };
+    if size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel == 0 || size.ws_ypixel == 0 {
+        return DEFAULT_CELL_SIZE;
+    }
+    (
+        (size.ws_xpixel / size.ws_col) as u32,
+        (size.ws_ypixel / size.ws_row) as u32,
+    )
+}
+
+fn opaque(pixel: &Rgba<u8>) -> bool {
+    pixel[3] >= 128
+}
+
+/// Draws two pixels per cell with `▀`, using the foreground for the top one and
+/// the background for the bottom one.
+fn blocks(image: &RgbaImage) -> String {
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let top = image.get_pixel(x, y);
+            let bottom = image.get_pixel(x, y + 1);
+            let color = |pixel: &Rgba<u8>| Color::Hex(pixel[0], pixel[1], pixel[2]);
+            let (fg, bg, block) = match (opaque(top), opaque(bottom)) {
+                (false, false) => (Color::Default, Color::Default, ' '),
+                (true, false) => (color(top), Color::Default, '▀'),
+                (false, true) => (color(bottom), Color::Default, '▄'),
+                (true, true) => (color(top), color(bottom), '▀'),
+            };
+            // Colors go through `Style` so they are downsampled when needed.
+            let style = Style {
+                fg,
+                bg,
+                ..Default::default()
+            };
+            out.push_str(reset());
+            out.push_str(&style.format_start());
+            out.push(block);
+        }
+        out.push_str(reset());
+        out.push('\n');
+    }
+    out
+}
+
+/// The index of the closest color in a 6×6×6 color cube, or `None` for a
+/// transparent pixel.
+fn cube_index(pixel: &Rgba<u8>) -> Option<usize> {
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    opaque(pixel).then(|| level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]))
+}
+
+/// Encodes `image` as sixels, with its colors reduced to a 6×6×6 color cube.
+fn sixel(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let mut out = format!("\x1bP0;1q\"1;1;{width};{height}");
+    for i in 0..216 {
+        let _ = write!(
+            out,
+            "#{i};2;{};{};{}",
+            i / 36 * 20,
+            i / 6 % 6 * 20,
+            i % 6 * 20
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+        let mut used = [false; 216];
+        for y in rows.clone() {
+            for x in 0..width {
+                if let Some(i) = cube_index(image.get_pixel(x, y)) {
+                    used[i] = true;
+                }
+            }
+        }
+        for color in (0..216).filter(|i| used[*i]) {
+            let _ = write!(out, "#{color}");
+            let mut run = (0, '?');
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|y| cube_index(image.get_pixel(x, *y)) == Some(color))
+                    .fold(0, |bits, y| bits | 1 << (y - band));
+                let sixel = char::from(63 + bits as u8);
+                if sixel == run.1 {
+                    run.0 += 1;
+                } else {
+                    push_run(&mut out, run);
+                    run = (1, sixel);
+                }
+            }
+            push_run(&mut out, run);
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_run(out: &mut String, (count, sixel): (usize, char)) {
+    if count > 3 {
+        let _ = write!(out, "!{count}{sixel}");
+    } else {
+        out.extend((0..count).map(|_| sixel));
+    }
+}
+
+/// Sends `image` with the kitty graphics protocol, scaled to `cols`×`rows`
+/// cells and leaving the cursor where it was.
+fn kitty(image: &RgbaImage, cols: u32, rows: u32) -> String {
+    const CHUNK_SIZE: usize = 4096;
+
+    let data = base64(image.as_raw());
+    let chunks: Vec<_> = data.as_bytes().chunks(CHUNK_SIZE).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).expect("base64 is ASCII");
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=32,s={},v={},c={cols},r={rows},C=1,q=2,m={more};{chunk}\x1b\\",
+                image.width(),
+                image.height()
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};{chunk}\x1b\\");
+        }
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,108 @@
+use std::io;
+
+use crate::config::{style::Color, system};
+
+/// A logo that ships with dyn-fetch. The art uses `${c1}`, `${c2}` and so on,
+/// which default to `colors` but can be changed in `[art.colors]`.
+pub struct Logo {
+    /// The `ID`s from `/etc/os-release` this logo is for. The first one is the
+    /// name used in the config.
+    pub ids: &'static [&'static str],
+    pub art: &'static str,
+    pub colors: &'static [Color],
+}
+
+pub const LOGOS: &[Logo] = &[
+    Logo {
+        ids: &["alpine"],
+        art: include_str!("logos/alpine.txt"),
+        colors: &[Color::Blue],
+    },
+    Logo {
+        ids: &["arch", "archarm"],
+        art: include_str!("logos/arch.txt"),
+        colors: &[Color::Cyan],
+    },
+    Logo {
+        ids: &["debian"],
+        art: include_str!("logos/debian.txt"),
+        colors: &[Color::Red],
+    },
+    Logo {
+        ids: &["fedora"],
+        art: include_str!("logos/fedora.txt"),
+        colors: &[Color::Blue, Color::White],
+    },
+    Logo {
+        ids: &["gentoo"],
+        art: include_str!("logos/gentoo.txt"),
+        colors: &[Color::Magenta, Color::White],
+    },
+    Logo {
+        ids: &["linuxmint", "mint"],
+        art: include_str!("logos/linuxmint.txt"),
+        colors: &[Color::Green, Color::White],
+    },
+    Logo {
+        ids: &["manjaro"],
+        art: include_str!("logos/manjaro.txt"),
+        colors: &[Color::Green],
+    },
+    Logo {
+        ids: &["nixos", "nix"],
+        art: include_str!("logos/nixos.txt"),
+        colors: &[Color::Blue, Color::Cyan],
+    },
+    Logo {
+        ids: &["opensuse", "opensuse-leap", "opensuse-tumbleweed", "suse"],
+        art: include_str!("logos/opensuse.txt"),
+        colors: &[Color::Green, Color::White],
+    },
+    Logo {
+        ids: &["ubuntu"],
+        art: include_str!("logos/ubuntu.txt"),
+        colors: &[Color::Red],
+    },
+    Logo {
+        ids: &["void"],
+        art: include_str!("logos/void.txt"),
+        colors: &[Color::Green, Color::White],
+    },
+    Logo {
+        ids: &["linux"],
+        art: include_str!("logos/linux.txt"),
+        colors: &[Color::White, Color::Default, Color::Yellow],
+    },
+];
+
+/// Finds the logo called `name`. `auto` picks the logo for this system from
+/// the `ID` and `ID_LIKE` of `/etc/os-release`, falling back to Tux.
+pub fn find(name: &str) -> io::Result<&'static Logo> {
+    if name == "auto" {
+        let release = system::os_release().unwrap_or_default();
+        let ids = release
+            .iter()
+            .filter(|(key, _)| key == "ID")
+            .chain(release.iter().filter(|(key, _)| key == "ID_LIKE"))
+            .flat_map(|(_, ids)| ids.split_whitespace());
+        let logo = ids
+            .filter_map(by_id)
+            .next()
+            .unwrap_or(&LOGOS[LOGOS.len() - 1]);
+        return Ok(logo);
+    }
+    by_id(name).ok_or_else(|| {
+        let names: Vec<_> = LOGOS.iter().map(|logo| logo.ids[0]).collect();
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "There is no built-in logo called `{name}`. Try `auto` or one of: {}.",
+                names.join(", ")
+            ),
+        )
+    })
+}
+
+fn by_id(id: &str) -> Option<&'static Logo> {
+    LOGOS.iter().find(|logo| logo.ids.contains(&id))
+}
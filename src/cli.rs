@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use serde::de::{DeserializeOwned, IntoDeserializer};
+
+use crate::{config::ArtPosition, layout::LayoutKind};
+
+pub const HELP: &str = "\
+Usage: dyn-fetch [OPTIONS]
+
+Options:
+  -c, --config <path>         Read the config from <path>
+      --art <path>            Read the art from <path>, relative to the current directory
+      --art-layout <layout>   Where to put the art: none, left, top, right or bottom
+      --layout <layout>       How to lay out the info: rectangle or table
+      --no-color              Don't print any escape codes
+      --print-default-config  Print the built-in config and exit
+      --list-sources          List the sources that can be used in `[[info]]` and exit
+  -h, --help                  Print this message and exit
+  -V, --version               Print the version and exit
+";
+
+/// Every `type` that can be used in `[[info]]`, with a description and the
+/// fields it exposes to `format`.
+pub const SOURCES: &[(&str, &str, &str)] = &[
+    ("separator", "a horizontal line", ""),
+    ("const", "fixed `text`", ""),
+    ("command", "the output of `cmd` run with `args`", "output"),
+    ("nu", "the output of a NuShell expression", "output"),
+    (
+        "hostname",
+        "the current user and hostname",
+        "username hostname",
+    ),
+    ("env_variable", "an environment variable", "name value"),
+    (
+        "os",
+        "the distribution",
+        "name pretty_name id version codename arch",
+    ),
+    ("kernel", "the running kernel", "name release version arch"),
+    (
+        "uptime",
+        "time since boot",
+        "pretty days hours minutes seconds total_seconds",
+    ),
+    ("cpu", "the processor model", "model cores threads mhz ghz"),
+    (
+        "memory",
+        "RAM usage in MiB",
+        "used total free used_gib total_gib percent",
+    ),
+    (
+        "swap",
+        "swap usage in MiB",
+        "used total free used_gib total_gib percent",
+    ),
+    ("load_average", "the system load", "one five fifteen"),
+    (
+        "disk",
+        "usage of the filesystem at `mount`",
+        "mount used total free used_gib total_gib free_gib percent",
+    ),
+    ("battery", "charge of a battery", "name capacity status"),
+    (
+        "packages",
+        "installed package counts from dpkg, pacman, apk, flatpak, snap and nix",
+        "total summary dpkg pacman apk flatpak snap nix",
+    ),
+];
+
+pub enum Command {
+    Fetch(Args),
+    Help,
+    Version,
+    PrintDefaultConfig,
+    ListSources,
+}
+
+/// Options that override the config for a single run.
+#[derive(Default)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub art: Option<PathBuf>,
+    pub art_layout: Option<ArtPosition>,
+    pub layout: Option<LayoutKind>,
+    pub no_color: bool,
+}
+
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.into())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("`{flag}` expects a value."))
+        };
+        match flag.as_str() {
+            "--no-color" | "--print-default-config" | "--list-sources" | "--help" | "--version"
+                if inline_value.is_some() =>
+            {
+                return Err(unexpected(&arg));
+            }
+            "-c" | "--config" => parsed.config = Some(PathBuf::from(value()?)),
+            "--art" => parsed.art = Some(PathBuf::from(value()?)),
+            "--art-layout" => parsed.art_layout = Some(parse_enum(&flag, &value()?)?),
+            "--layout" => parsed.layout = Some(parse_enum(&flag, &value()?)?),
+            "--no-color" => parsed.no_color = true,
+            "--print-default-config" => return Ok(Command::PrintDefaultConfig),
+            "--list-sources" => return Ok(Command::ListSources),
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            _ => return Err(unexpected(&flag)),
+        }
+    }
+    Ok(Command::Fetch(parsed))
+}
+
+fn unexpected(arg: &str) -> String {
+    format!("Unexpected argument `{arg}`. Run `dyn-fetch --help` for usage.")
+}
+
+fn parse_enum<T: DeserializeOwned>(flag: &str, value: &str) -> Result<T, String> {
+    T::deserialize(value.into_deserializer())
+        .map_err(|err: serde::de::value::Error| format!("Invalid value for `{flag}`: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn takes_values_inline_or_separately() {
+        for args in [
+            &["--config", "a.toml"][..],
+            &["--config=a.toml"],
+            &["-c", "a.toml"],
+        ] {
+            let Ok(Command::Fetch(parsed)) = parse_args(args) else {
+                panic!("{args:?} didn't parse");
+            };
+            assert_eq!(parsed.config, Some(PathBuf::from("a.toml")));
+        }
+        assert!(parse_args(&["--config"]).is_err());
+    }
+
+    #[test]
+    fn rejects_values_for_flags() {
+        for arg in [
+            "--no-color=false",
+            "--help=x",
+            "--version=x",
+            "--list-sources=x",
+            "--print-default-config=x",
+        ] {
+            assert_eq!(
+                parse_args(&[arg]).err(),
+                Some(format!(
+                    "Unexpected argument `{arg}`. Run `dyn-fetch --help` for usage."
+                ))
+            );
+        }
+        assert!(matches!(
+            parse_args(&["--no-color"]),
+            Ok(Command::Fetch(Args { no_color: true, .. }))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        assert!(parse_args(&["--colour"]).is_err());
+        assert!(parse_args(&["fetch"]).is_err());
+    }
+}
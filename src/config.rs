@@ -1,38 +1,221 @@
+pub mod command;
+pub mod condition;
+pub mod discovery;
+mod format;
+pub mod include;
 pub mod style;
+pub mod system;
 
-use std::{env, io, process::Command};
+use std::{collections::HashMap, env, io, path::PathBuf, process::Command, time::Duration};
 
-use crate::layout::Layout;
+use crate::{
+    art::image::ImageProtocol,
+    layout::{Layout, Overflow},
+};
+use condition::When;
+use format::Fields;
 use serde::Deserialize;
-use style::{Style, Text};
+use style::{Color, ColorChoice, Style, Text};
 
 #[derive(Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub art_layout: ArtPosition,
+    #[serde(default)]
+    pub art_align: ArtAlign,
+    #[serde(default)]
+    pub art_kind: ArtKind,
+    /// A file, or a directory or glob like `logos/*.txt` to pick one from.
     #[serde(default = "default_art_path")]
-    pub art_path: String,
+    pub art_path: PathBuf,
+    #[serde(default)]
+    pub art_pick: ArtPick,
+    /// A shell command, like `figlet $(hostname)`, whose output is used as the
+    /// art instead of `art_path`. Placeholders in the output that aren't known
+    /// colors are left as they are.
+    pub art_command: Option<String>,
+    #[serde(default)]
+    pub art: ArtConfig,
     pub layout: Layout,
     pub info: Vec<Info>,
+    pub timeout_ms: Option<u64>,
+    #[serde(default = "default_timeout_text")]
+    pub timeout_text: Text,
+    #[serde(default)]
+    pub on_error: OnError,
+    #[serde(default = "default_error_text")]
+    pub error_text: Text,
+    #[serde(default)]
+    pub color: ColorChoice,
+    /// How many columns Nerd Font icons take up, which depends on the font.
+    pub nerd_font_width: Option<usize>,
 }
 
-#[derive(Deserialize, Default)]
+impl Config {
+    /// Fills in per-entry options that were left unset with the global defaults.
+    pub fn apply_defaults(&mut self) {
+        for info in &mut self.info {
+            info.on_error.get_or_insert(self.on_error);
+            info.error_text
+                .get_or_insert_with(|| self.error_text.clone());
+            if let Value::Command {
+                timeout_ms,
+                timeout_text,
+                ..
+            }
+            | Value::Nu {
+                timeout_ms,
+                timeout_text,
+                ..
+            } = &mut info.value
+            {
+                if timeout_ms.is_none() {
+                    *timeout_ms = self.timeout_ms;
+                }
+                timeout_text.get_or_insert_with(|| self.timeout_text.clone());
+            }
+            if info.when.timeout_ms.is_none() {
+                info.when.timeout_ms = match &info.value {
+                    Value::Command { timeout_ms, .. } | Value::Nu { timeout_ms, .. } => *timeout_ms,
+                    _ => self.timeout_ms,
+                };
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum ArtPosition {
     #[default]
     None,
     Left,
     Top,
+    Right,
+    Bottom,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ArtConfig {
+    /// The name of a built-in logo to use instead of `art_path`, or `auto` to
+    /// pick one for this system.
+    pub builtin: Option<String>,
+    /// The number of columns an image takes up.
+    pub width: Option<u16>,
+    /// The number of rows an image takes up. Follows from `width` if unset.
+    pub height: Option<u16>,
+    #[serde(default)]
+    pub protocol: ImageProtocol,
+    /// The terminal width below which art on the left or right moves to the
+    /// top. Defaults to the width of the art and the info side by side.
+    pub top_below: Option<usize>,
+    /// The terminal width below which the art is left out. Defaults to the
+    /// width of the art.
+    pub hide_below: Option<usize>,
+    /// Styles for the `${name}` placeholders in the art.
+    #[serde(default)]
+    pub colors: HashMap<String, Style>,
+}
+
+/// What kind of file `art_path` points to.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtKind {
+    #[default]
+    Text,
+    /// A PNG or JPEG image.
+    Image,
 }
 
-fn default_art_path() -> String {
-    String::from("art.txt")
+/// How a file is picked when `art_path` is a directory or glob.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtPick {
+    /// A different file every run.
+    #[default]
+    Random,
+    /// The next file every day.
+    Daily,
+    /// The same file every time on a machine, depending on its hostname.
+    ByHost,
+}
+
+/// Where the shorter of the art and the info sits next to the taller one.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtAlign {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+impl ArtAlign {
+    /// How many lines down a block of `len` lines starts in a space of `height`.
+    pub fn offset(self, len: usize, height: usize) -> usize {
+        let space = height.saturating_sub(len);
+        match self {
+            ArtAlign::Top => 0,
+            ArtAlign::Center => space / 2,
+            ArtAlign::Bottom => space,
+        }
+    }
+}
+
+fn default_art_path() -> PathBuf {
+    PathBuf::from("art.txt")
+}
+
+pub fn default_error_text() -> Text {
+    Text::Styled {
+        text: String::from("unavailable"),
+        style: Style {
+            fg: Color::Red,
+            dim: true,
+            ..Default::default()
+        },
+        link: None,
+    }
+}
+
+fn default_timeout_text() -> Text {
+    Text::Styled {
+        text: String::from("timed out"),
+        style: Style {
+            dim: true,
+            ..Default::default()
+        },
+        link: None,
+    }
+}
+
+/// What to do with an entry whose value could not be resolved.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Stop and report the error without printing anything.
+    #[default]
+    Abort,
+    /// Leave the entry out.
+    Skip,
+    /// Show `error_text` in place of the value.
+    Fallback,
 }
 
 #[derive(Deserialize)]
 pub struct Info {
     #[serde(default)]
     pub label: Text,
+    pub on_error: Option<OnError>,
+    pub error_text: Option<Text>,
+    #[serde(default, deserialize_with = "When::deserialize_checked")]
+    pub when: When,
+    /// A URL that the value links to.
+    pub link: Option<String>,
+    /// The widest the value may be.
+    pub max_width: Option<usize>,
+    /// Defaults to the `overflow` of the layout.
+    pub overflow: Option<Overflow>,
     #[serde(flatten)]
     pub value: Value,
 }
@@ -49,22 +232,77 @@ pub enum Value {
         #[serde(default)]
         args: Vec<String>,
         postfix: Option<Text>,
+        format: Option<String>,
         style: Option<Style>,
+        timeout_ms: Option<u64>,
+        timeout_text: Option<Text>,
     },
     Nu {
         exec: String,
         postfix: Option<Text>,
+        format: Option<String>,
         style: Option<Style>,
+        timeout_ms: Option<u64>,
+        timeout_text: Option<Text>,
     },
     Hostname {
         username: Option<Style>,
         hostname: Option<Style>,
         delimiter: Option<Text>,
+        format: Option<String>,
     },
     EnvVariable {
         name: String,
+        format: Option<String>,
         style: Option<Style>,
     },
+    Os {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Kernel {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Uptime {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Cpu {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Memory {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Swap {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    LoadAverage {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Disk {
+        #[serde(default = "default_mount")]
+        mount: String,
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Battery {
+        name: Option<String>,
+        style: Option<Style>,
+        format: Option<String>,
+    },
+    Packages {
+        style: Option<Style>,
+        format: Option<String>,
+    },
+}
+
+fn default_mount() -> String {
+    String::from("/")
 }
 
 impl TryInto<Option<Text>> for Value {
@@ -77,96 +315,88 @@ impl TryInto<Option<Text>> for Value {
                 cmd: command,
                 args,
                 postfix,
+                format,
                 style,
+                timeout_ms,
+                timeout_text,
             } => {
-                let mut cmd_out = String::from_utf8(
-                    Command::new(&command).args(args).output()?.stdout,
-                )
-                .map_err(|_| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("Invalid UTF8 was outputted by the command `{}`.", command),
-                    )
+                let Some(output) =
+                    command::output(Command::new(&command).args(args), timeout(timeout_ms))?
+                else {
+                    return Ok(Some(timeout_text.unwrap_or_else(default_timeout_text)));
+                };
+                let mut cmd_out = String::from_utf8(output.stdout).map_err(|_| {
+                    io::Error::other(format!(
+                        "Invalid UTF8 was outputted by the command `{}`.",
+                        command
+                    ))
                 })?;
                 cmd_out.truncate(cmd_out.trim_end().len());
-                let cmd_out = if let Some(style) = style {
-                    Text::Styled {
-                        text: cmd_out,
-                        style,
-                    }
-                } else {
-                    Text::Unstyled(cmd_out)
-                };
-                let mut vec = vec![cmd_out];
+                let mut text = Fields::new("{output}")
+                    .with("output", cmd_out)
+                    .format(format.as_deref(), style)?;
                 if let Some(postfix) = postfix {
-                    vec.push(postfix);
+                    text.append(postfix);
                 }
-                Ok(Some(Text::Combine(vec)))
+                Ok(Some(text))
             }
             Self::Nu {
                 exec,
                 postfix,
+                format,
                 style,
+                timeout_ms,
+                timeout_text,
             } => {
-                let mut cmd_out =
-                    String::from_utf8(Command::new("nu").args(["-c", &exec]).output()?.stdout)
-                        .map_err(|_| {
-                            io::Error::new(
-                                io::ErrorKind::Other,
-                                format!(
-                                    "Invalid UTF8 was outputted by the NuShell expression `{}`.",
-                                    exec
-                                ),
-                            )
-                        })?;
-                cmd_out.truncate(cmd_out.trim_end().len());
-                let cmd_out = if let Some(style) = style {
-                    Text::Styled {
-                        text: cmd_out,
-                        style,
-                    }
-                } else {
-                    Text::Unstyled(cmd_out)
+                let Some(output) =
+                    command::output(Command::new("nu").args(["-c", &exec]), timeout(timeout_ms))?
+                else {
+                    return Ok(Some(timeout_text.unwrap_or_else(default_timeout_text)));
                 };
-                let mut vec = vec![cmd_out];
+                let mut cmd_out = String::from_utf8(output.stdout).map_err(|_| {
+                    io::Error::other(format!(
+                        "Invalid UTF8 was outputted by the NuShell expression `{}`.",
+                        exec
+                    ))
+                })?;
+                cmd_out.truncate(cmd_out.trim_end().len());
+                let mut text = Fields::new("{output}")
+                    .with("output", cmd_out)
+                    .format(format.as_deref(), style)?;
                 if let Some(postfix) = postfix {
-                    vec.push(postfix);
+                    text.append(postfix);
                 }
-                Ok(Some(Text::Combine(vec)))
+                Ok(Some(text))
             }
             Self::Hostname {
                 username: username_style,
                 hostname: hostname_style,
                 delimiter,
+                format,
             } => {
-                let mut hostname = String::from_utf8(Command::new("hostname").output()?.stdout)
-                    .map_err(|_| {
-                        io::Error::new(
-                            io::ErrorKind::Other,
-                            "Invalid UTF8 was outputted by the command `hostname`.",
-                        )
-                    })?;
-                hostname.truncate(hostname.trim_end().len());
+                let hostname = system::hostname()?;
+                let username = system::username()?;
+                if let Some(format) = format {
+                    return Fields::new("")
+                        .with_style("username", username, username_style)
+                        .with_style("hostname", hostname, hostname_style)
+                        .format(Some(&format), None)
+                        .map(Some);
+                }
                 let hostname = if let Some(style) = hostname_style {
                     Text::Styled {
                         text: hostname,
                         style,
+                        link: None,
                     }
                 } else {
                     Text::Unstyled(hostname)
                 };
-                let mut username = String::from_utf8(Command::new("whoami").output()?.stdout)
-                    .map_err(|_| {
-                        io::Error::new(
-                            io::ErrorKind::Other,
-                            "Invalid UTF8 was outputted by the command `whoami`.",
-                        )
-                    })?;
-                username.truncate(username.trim_end().len());
                 let username = if let Some(style) = username_style {
                     Text::Styled {
                         text: username,
                         style,
+                        link: None,
                     }
                 } else {
                     Text::Unstyled(username)
@@ -177,22 +407,54 @@ impl TryInto<Option<Text>> for Value {
                     hostname,
                 ])))
             }
-            Self::EnvVariable { name, style } => {
+            Self::EnvVariable {
+                name,
+                format,
+                style,
+            } => {
                 let variable = env::var(&name).map_err(|_| {
                     io::Error::new(
                         io::ErrorKind::NotFound,
                         format!("Enviornment variable `{name}` was not found."),
                     )
                 })?;
-                if let Some(style) = style {
-                    Ok(Some(Text::Styled {
-                        text: variable,
-                        style,
-                    }))
-                } else {
-                    Ok(Some(Text::Unstyled(variable)))
-                }
+                Fields::new("{value}")
+                    .with("name", name)
+                    .with("value", variable)
+                    .format(format.as_deref(), style)
+                    .map(Some)
             }
+            Self::Os { style, format } => native(system::os()?, format, style),
+            Self::Kernel { style, format } => native(system::kernel()?, format, style),
+            Self::Uptime { style, format } => native(system::uptime()?, format, style),
+            Self::Cpu { style, format } => native(system::cpu()?, format, style),
+            Self::Memory { style, format } => native(system::memory()?, format, style),
+            Self::Swap { style, format } => native(system::swap()?, format, style),
+            Self::LoadAverage { style, format } => native(system::load_average()?, format, style),
+            Self::Disk {
+                mount,
+                style,
+                format,
+            } => native(system::disk(&mount)?, format, style),
+            Self::Battery {
+                name,
+                style,
+                format,
+            } => native(system::battery(name.as_deref())?, format, style),
+            Self::Packages { style, format } => native(system::packages()?, format, style),
         }
     }
 }
+
+fn native(
+    fields: Fields,
+    format: Option<String>,
+    style: Option<Style>,
+) -> io::Result<Option<Text>> {
+    fields.format(format.as_deref(), style).map(Some)
+}
+
+/// A timeout of zero means the command may run for as long as it likes.
+pub fn timeout(timeout_ms: Option<u64>) -> Option<Duration> {
+    timeout_ms.filter(|ms| *ms > 0).map(Duration::from_millis)
+}
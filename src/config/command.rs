@@ -0,0 +1,120 @@
+use std::{
+    io::{self, Read},
+    os::unix::process::CommandExt,
+    process::{Command, Output, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Runs `command` and returns its exit status and stdout, or `None` if it ran
+/// past `timeout` and was killed.
+///
+/// The command runs in its own process group, so a timeout also kills any
+/// processes it started that still hold its stdout open.
+pub fn output(command: &mut Command, timeout: Option<Duration>) -> io::Result<Option<Output>> {
+    let Some(timeout) = timeout else {
+        return command.output().map(Some);
+    };
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = sender.send(stdout.read_to_end(&mut buf).map(|_| buf));
+    });
+
+    let deadline = Instant::now() + timeout;
+    if let Ok(stdout) = receiver.recv_timeout(timeout) {
+        let stdout = stdout?;
+        // The child may close stdout and keep running, so it has until the same
+        // deadline to exit.
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(Some(Output {
+                    status,
+                    stdout,
+                    stderr: Vec::new(),
+                }));
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // SAFETY: `kill` has no memory safety requirements; a negative pid signals
+    // the process group the child leads.
+    unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+    child.wait()?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::Path, process};
+
+    const TIMEOUT: Duration = Duration::from_millis(300);
+
+    fn sh(script: &str) -> (io::Result<Option<Output>>, Duration) {
+        let start = Instant::now();
+        let output = output(Command::new("sh").args(["-c", script]), Some(TIMEOUT));
+        (output, start.elapsed())
+    }
+
+    #[test]
+    fn returns_stdout_of_fast_commands() {
+        let (output, _) = sh("echo fast");
+        let output = output.unwrap().unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"fast\n");
+
+        let output = super::output(Command::new("echo").arg("untimed"), None);
+        assert_eq!(output.unwrap().unwrap().stdout, b"untimed\n");
+    }
+
+    #[test]
+    fn times_out_when_stdout_closes_early() {
+        let (output, elapsed) = sh("echo a; exec >&-; sleep 5");
+        assert!(output.unwrap().is_none());
+        assert!(elapsed < TIMEOUT * 3, "took {elapsed:?}");
+    }
+
+    #[test]
+    fn times_out_when_a_child_holds_stdout() {
+        let pid_file = env::temp_dir().join(format!("dyn-fetch-{}-pid", process::id()));
+        let (output, elapsed) = sh(&format!(
+            "sleep 5 & echo $! > {}; echo b",
+            pid_file.display()
+        ));
+        assert!(output.unwrap().is_none());
+        assert!(elapsed < TIMEOUT * 3, "took {elapsed:?}");
+
+        // The background `sleep` is killed along with the shell, so it is gone
+        // or a zombie waiting to be reaped.
+        let pid = fs::read_to_string(&pid_file).unwrap();
+        let _ = fs::remove_file(&pid_file);
+        let stat = Path::new("/proc").join(pid.trim()).join("stat");
+        let killed = || {
+            fs::read_to_string(&stat).map_or(true, |stat| {
+                stat.rsplit_once(')')
+                    .is_some_and(|(_, rest)| rest.trim_start().starts_with(['Z', 'X']))
+            })
+        };
+        let deadline = Instant::now() + TIMEOUT;
+        while !killed() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+        }
+        assert!(killed(), "`sleep` is still running");
+    }
+}
@@ -0,0 +1,161 @@
+use std::{env, path::PathBuf, process::Command};
+
+use serde::{Deserialize, Deserializer, de};
+
+use super::{command, system, timeout};
+
+/// Conditions for showing an entry. Every condition that is set has to hold.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct When {
+    /// An environment variable that has to be set.
+    env: Option<String>,
+    /// The value that `env` has to be equal to.
+    env_equals: Option<String>,
+    /// A pattern the hostname has to match, where `*` matches any number of
+    /// characters and `?` matches one.
+    hostname: Option<String>,
+    /// A path that has to exist.
+    file_exists: Option<PathBuf>,
+    /// A command that has to exit successfully.
+    command: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    /// The `ID` from `/etc/os-release`.
+    os: Option<String>,
+    /// How long `command` may run before the condition counts as not holding.
+    /// Defaults to the `timeout_ms` of the entry.
+    pub timeout_ms: Option<u64>,
+}
+
+impl When {
+    /// Deserializes conditions, rejecting ones that could never be checked.
+    pub fn deserialize_checked<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let when = Self::deserialize(deserializer)?;
+        if when.env_equals.is_some() && when.env.is_none() {
+            return Err(de::Error::custom("`env_equals` needs `env` to be set."));
+        }
+        Ok(when)
+    }
+
+    pub fn holds(&self) -> bool {
+        if let Some(name) = &self.env {
+            let Ok(value) = env::var(name) else {
+                return false;
+            };
+            if self
+                .env_equals
+                .as_ref()
+                .is_some_and(|equals| *equals != value)
+            {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.hostname {
+            let Ok(hostname) = system::hostname() else {
+                return false;
+            };
+            if !matches(pattern, &hostname) {
+                return false;
+            }
+        }
+        if self.file_exists.as_ref().is_some_and(|path| !path.exists()) {
+            return false;
+        }
+        if let Some(os) = &self.os {
+            let Ok(release) = system::os_release() else {
+                return false;
+            };
+            if !release.iter().any(|(key, id)| key == "ID" && id == os) {
+                return false;
+            }
+        }
+        if let Some(command) = &self.command {
+            let output = command::output(
+                Command::new(command).args(&self.args),
+                timeout(self.timeout_ms),
+            );
+            if !output.is_ok_and(|output| output.is_some_and(|output| output.status.success())) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Matches `text` against a glob `pattern` supporting `*` and `?`.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume if the characters after the last `*` stop matching.
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_literals() {
+        assert!(matches("host", "host"));
+        assert!(!matches("host", "hos"));
+        assert!(!matches("host", "hosts"));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("work-*", "work-laptop"));
+        assert!(matches("work-*", "work-"));
+        assert!(!matches("work-*", "home-laptop"));
+        assert!(matches("*", ""));
+        assert!(matches("h?st", "host"));
+        assert!(!matches("h?st", "hst"));
+        assert!(matches("*.toml", "config.toml"));
+        assert!(!matches("*.toml", "config.toml.bak"));
+    }
+
+    #[test]
+    fn backtracks_after_a_star() {
+        assert!(matches("*ab", "aab"));
+        assert!(matches("a*b*c", "axbybzc"));
+        assert!(matches("*a*", "banana"));
+        assert!(!matches("a*b*c", "axbybz"));
+        assert!(matches("**x", "yx"));
+        assert!(matches("日*語", "日本語"));
+        assert!(matches("?本?", "日本語"));
+    }
+
+    #[test]
+    fn env_equals_needs_env() {
+        let when =
+            |source: &str| When::deserialize_checked(toml::Value::Table(source.parse().unwrap()));
+        assert!(when("env = \"HOME\"\nenv_equals = \"x\"").is_ok());
+        assert!(when("env_equals = \"x\"").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+/// Where the config was found.
+pub enum ConfigSource {
+    File(PathBuf),
+    /// None of the `tried` paths is a file, so the config compiled into the
+    /// binary is used.
+    Default {
+        tried: Vec<PathBuf>,
+    },
+}
+
+/// Returned when `$DYN_FETCH_CONFIG` is set but is not a file.
+pub struct NotFound {
+    pub path: PathBuf,
+}
+
+impl Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`$DYN_FETCH_CONFIG` is set to `{}`, which is not a file.",
+            self.path.display()
+        )
+    }
+}
+
+/// Uses `$DYN_FETCH_CONFIG` if it is set, and otherwise searches
+/// `$XDG_CONFIG_HOME/dyn-fetch/config.toml`, then each directory in
+/// `$XDG_CONFIG_DIRS`.
+pub fn discover() -> Result<ConfigSource, NotFound> {
+    // Someone asking for a specific config would rather hear that it's missing
+    // than get another one.
+    if let Some(path) = env::var_os("DYN_FETCH_CONFIG").filter(|path| !path.is_empty()) {
+        let path = PathBuf::from(path);
+        return if path.is_file() {
+            Ok(ConfigSource::File(path))
+        } else {
+            Err(NotFound { path })
+        };
+    }
+
+    let tried: Vec<PathBuf> = dirs::config_dir()
+        .into_iter()
+        .chain(config_dirs())
+        .map(|dir| in_dir(&dir))
+        .collect();
+    Ok(match tried.iter().find(|path| path.is_file()) {
+        Some(path) => ConfigSource::File(path.clone()),
+        None => ConfigSource::Default { tried },
+    })
+}
+
+fn in_dir(dir: &Path) -> PathBuf {
+    dir.join("dyn-fetch").join("config.toml")
+}
+
+/// The directories in `$XDG_CONFIG_DIRS`. Relative paths are ignored, as the
+/// spec requires.
+fn config_dirs() -> Vec<PathBuf> {
+    let dirs = env::var_os("XDG_CONFIG_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".into());
+    env::split_paths(&dirs)
+        .filter(|dir| dir.is_absolute())
+        .collect()
+}
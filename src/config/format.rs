@@ -0,0 +1,253 @@
+use std::{io, mem};
+
+use serde::{Deserialize, de::IntoDeserializer};
+
+use super::style::{Color, Style, Text, Underline};
+
+/// Named values exposed by a source, along with the format that is used when
+/// the config doesn't specify one.
+///
+/// Formats replace every `{name}` with the matching field, and wrap
+/// `[text](style)` spans in a style such as `fg=red bg=black bold`. Braces and
+/// brackets can be escaped by doubling them.
+pub struct Fields {
+    default_format: &'static str,
+    values: Vec<(&'static str, String, Option<Style>)>,
+}
+
+impl Fields {
+    pub fn new(default_format: &'static str) -> Self {
+        Self {
+            default_format,
+            values: Vec::new(),
+        }
+    }
+
+    pub fn with(self, name: &'static str, value: impl ToString) -> Self {
+        self.with_style(name, value, None)
+    }
+
+    /// Adds a field that keeps its own style when it is used outside of a span.
+    pub fn with_style(
+        mut self,
+        name: &'static str,
+        value: impl ToString,
+        style: Option<Style>,
+    ) -> Self {
+        self.values.push((name, value.to_string(), style));
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<(&str, Option<Style>)> {
+        self.values
+            .iter()
+            .find(|(n, _, _)| *n == name)
+            .map(|(_, value, style)| (value.as_str(), *style))
+    }
+
+    /// Renders `template`, or the default format if there is none. Text outside
+    /// of spans uses `style`.
+    pub fn format(&self, template: Option<&str>, style: Option<Style>) -> io::Result<Text> {
+        let template = template.unwrap_or(self.default_format);
+        let error = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid format `{template}`: {message}."),
+            )
+        };
+
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut in_span = false;
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            let rest = chars.as_str();
+            match c {
+                '{' | '}' | '[' | ']' if rest.starts_with(c) => {
+                    chars.next();
+                    text.push(c);
+                }
+                '{' => {
+                    let (name, after) =
+                        rest.split_once('}').ok_or_else(|| error("unclosed `{`"))?;
+                    let (value, field_style) = self
+                        .get(name)
+                        .ok_or_else(|| error(&format!("unknown field `{name}`")))?;
+                    match field_style {
+                        Some(field_style) if !in_span => {
+                            flush(&mut segments, &mut text, style);
+                            segments.push(Text::Styled {
+                                text: value.to_string(),
+                                style: field_style,
+                                link: None,
+                            });
+                        }
+                        _ => text.push_str(value),
+                    }
+                    chars = after.chars();
+                }
+                '[' if in_span => return Err(error("spans can't be nested")),
+                '[' => {
+                    flush(&mut segments, &mut text, style);
+                    in_span = true;
+                }
+                ']' if in_span => {
+                    let (spec, after) = rest
+                        .strip_prefix('(')
+                        .and_then(split_closing_paren)
+                        .ok_or_else(|| error("expected a `(style)` after `]`"))?;
+                    let span_style = parse_style(spec).map_err(|message| error(&message))?;
+                    flush(&mut segments, &mut text, Some(span_style));
+                    in_span = false;
+                    chars = after.chars();
+                }
+                '}' | ']' => return Err(error(&format!("unmatched `{c}`"))),
+                _ => text.push(c),
+            }
+        }
+        if in_span {
+            return Err(error("unclosed `[`"));
+        }
+        flush(&mut segments, &mut text, style);
+        Ok(Text::Combine(segments))
+    }
+}
+
+fn flush(segments: &mut Vec<Text>, text: &mut String, style: Option<Style>) {
+    if text.is_empty() {
+        return;
+    }
+    let text = mem::take(text);
+    segments.push(if let Some(style) = style {
+        Text::Styled {
+            text,
+            style,
+            link: None,
+        }
+    } else {
+        Text::Unstyled(text)
+    });
+}
+
+/// Splits `text` at the `)` that closes a `(` before it, skipping over nested
+/// parentheses like the ones in `fg=rgb(255, 0, 0)`.
+fn split_closing_paren(text: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some((&text[..i], &text[i + 1..])),
+            ')' => depth -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Splits a style into words at whitespace that isn't inside parentheses.
+fn words(spec: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in spec.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth <= 0 => {
+                words.push(&spec[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    words.push(&spec[start..]);
+    words.retain(|word| !word.is_empty());
+    words
+}
+
+/// Parses a span style like `fg=red bold`. A bare color sets the foreground.
+fn parse_style(spec: &str) -> Result<Style, String> {
+    let color = |value: &str| {
+        Color::deserialize(value.into_deserializer())
+            .map_err(|err: serde::de::value::Error| err.to_string())
+    };
+    let mut style = Style::default();
+    for word in words(spec) {
+        match word.split_once('=') {
+            Some(("fg", value)) => style.fg = color(value)?,
+            Some(("bg", value)) => style.bg = color(value)?,
+            Some(("underline_color", value)) => style.underline_color = color(value)?,
+            Some(("underline", value)) => {
+                style.underline = Underline::deserialize(value.into_deserializer())
+                    .map_err(|err: serde::de::value::Error| err.to_string())?;
+            }
+            Some((key, _)) => return Err(format!("unknown style key `{key}`")),
+            None => match word {
+                "bold" => style.bold = true,
+                "italic" => style.italic = true,
+                "dim" => style.dim = true,
+                "underline" => style.underline = Underline::Single,
+                "strikethrough" => style.strikethrough = true,
+                "blink" => style.blink = true,
+                "reverse" => style.reverse = true,
+                "hidden" => style.hidden = true,
+                "overline" => style.overline = true,
+                _ => style.fg = color(word)?,
+            },
+        }
+    }
+    Ok(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(fields: &Fields, template: &str) -> String {
+        fields.format(Some(template), None).unwrap().into()
+    }
+
+    #[test]
+    fn replaces_fields() {
+        let fields = Fields::new("{a}").with("a", "x").with("b", "y");
+        assert_eq!(render(&fields, "{a}-{b}"), "x-y");
+        assert_eq!(String::from(fields.format(None, None).unwrap()), "x");
+    }
+
+    #[test]
+    fn doubled_brackets_and_braces_are_literal() {
+        let fields = Fields::new("{a}").with("a", "x");
+        assert_eq!(render(&fields, "[[{{a}}]]"), "[{a}]");
+        assert_eq!(render(&fields, "[[{a}]]"), "[x]");
+    }
+
+    #[test]
+    fn span_styles_can_contain_parentheses() {
+        let fields = Fields::new("{a}").with("a", "x");
+        assert_eq!(
+            render(&fields, "[{a}](fg=rgb(255, 0, 0) bold) (y)"),
+            "\x1b[38;2;255;0;0;1mx\x1b[0m (y)"
+        );
+        assert_eq!(
+            render(&fields, "[{a}](fg=hsl(0, 100%, 50%))"),
+            "\x1b[38;2;255;0;0mx\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let fields = Fields::new("{a}").with("a", "x");
+        for template in [
+            "{a",
+            "{b}",
+            "a}",
+            "[a",
+            "[a]",
+            "[a](red",
+            "[[a](red)]",
+            "[a](nope)",
+        ] {
+            assert!(fields.format(Some(template), None).is_err(), "{template}");
+        }
+    }
+}
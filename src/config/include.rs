@@ -0,0 +1,380 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use super::{Config, Info, system};
+
+/// How the `info` of a file combines with the `info` of the files it includes.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum InfoMerge {
+    /// Add this file's entries after the included ones.
+    #[default]
+    Append,
+    /// Add this file's entries before the included ones.
+    Prepend,
+    /// Use only this file's entries, if it has any.
+    Replace,
+}
+
+/// Parses a config, merging in the files listed in its `include` key. Include
+/// paths are relative to the file that lists them, and may use `${VAR}` to
+/// refer to environment variables. A missing include is an error, unless its
+/// path uses `${VAR}` or ends in `?`, so a file can be listed for hosts that
+/// don't all have one.
+///
+/// Included files are merged in order, and the including file is merged on top
+/// of them. Tables are merged key by key, with later values replacing earlier
+/// ones, except for `info`, which is combined as `info_merge` says.
+pub fn parse(source: &str, path: &Path) -> io::Result<Config> {
+    let table = parse_table(source, path)?;
+    if !table.contains_key("include") {
+        // Deserialize the source itself, so errors point at a line and column.
+        return toml::from_str(source).map_err(|err| invalid(path, &format!("{err}")));
+    }
+
+    let mut stack = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+    let (table, origins) = resolve(table, path, &mut stack)?;
+    table.clone().try_into().map_err(|err| {
+        // The merged table has no source to point into, so find the entry that
+        // failed and name the file it came from instead.
+        let Some(Value::Array(info)) = table.get("info") else {
+            return invalid(path, &format!("{err}"));
+        };
+        info.iter()
+            .zip(&origins)
+            .enumerate()
+            .find_map(|(i, (entry, origin))| {
+                let err = Info::deserialize(entry.clone()).err()?;
+                let index = origins[..i].iter().filter(|o| *o == origin).count();
+                Some(invalid(origin, &format!("info[{index}]: {err}")))
+            })
+            .unwrap_or_else(|| invalid(path, &format!("{err}")))
+    })
+}
+
+fn parse_table(source: &str, path: &Path) -> io::Result<Table> {
+    source
+        .parse()
+        .map_err(|err| invalid(path, &format!("{err}")))
+}
+
+fn invalid(path: &Path, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "Invalid config `{}`: {}",
+            path.display(),
+            message.trim_end()
+        ),
+    )
+}
+
+/// Resolves the includes of `table`, returning the merged table and the file
+/// that each of its `info` entries came from.
+fn resolve(
+    mut table: Table,
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+) -> io::Result<(Table, Vec<PathBuf>)> {
+    let includes = match table.remove("include") {
+        None => Vec::new(),
+        Some(Value::String(include)) => vec![include],
+        Some(Value::Array(includes)) => includes
+            .into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(include),
+                _ => Err(invalid(path, "`include` must only contain strings.")),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(invalid(path, "`include` must be a list of paths.")),
+    };
+    let info_merge = table
+        .remove("info_merge")
+        .map(InfoMerge::deserialize)
+        .transpose()
+        .map_err(|err| invalid(path, &format!("`info_merge`: {err}")))?
+        .unwrap_or_default();
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut base = Table::new();
+    let mut base_origins = Vec::new();
+    for include in includes {
+        let (include, optional) = match include.strip_suffix('?') {
+            Some(include) => (include, true),
+            None => (include.as_str(), include.contains("${")),
+        };
+        let include_path = dir.join(expand(include).map_err(|err| invalid(path, &err))?);
+        let source = match fs::read_to_string(&include_path) {
+            Ok(source) => source,
+            Err(err) if optional && err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("Could not read `{}`: {err}", include_path.display()),
+                ));
+            }
+        };
+        let canonical = include_path.canonicalize()?;
+        if stack.contains(&canonical) {
+            return Err(invalid(
+                path,
+                &format!("`{}` includes itself.", include_path.display()),
+            ));
+        }
+        stack.push(canonical);
+        let (included, origins) =
+            resolve(parse_table(&source, &include_path)?, &include_path, stack)?;
+        stack.pop();
+        merge(&mut base, included, InfoMerge::Append);
+        base_origins.extend(origins);
+    }
+    let origins = match table.get("info") {
+        Some(Value::Array(info)) => vec![path.to_path_buf(); info.len()],
+        _ => Vec::new(),
+    };
+    match info_merge {
+        InfoMerge::Append => base_origins.extend(origins),
+        InfoMerge::Prepend => {
+            let mut origins = origins;
+            origins.append(&mut base_origins);
+            base_origins = origins;
+        }
+        InfoMerge::Replace if table.contains_key("info") => base_origins = origins,
+        InfoMerge::Replace => {}
+    }
+    merge(&mut base, table, info_merge);
+    Ok((base, base_origins))
+}
+
+fn merge(base: &mut Table, over: Table, info_merge: InfoMerge) {
+    for (key, value) in over {
+        match (key.as_str(), base.get_mut(&key), value) {
+            ("info", Some(Value::Array(base_info)), Value::Array(mut info)) => match info_merge {
+                InfoMerge::Append => base_info.append(&mut info),
+                InfoMerge::Prepend => {
+                    info.append(base_info);
+                    *base_info = info;
+                }
+                InfoMerge::Replace => *base_info = info,
+            },
+            (_, Some(Value::Table(base_table)), Value::Table(table)) => {
+                merge(base_table, table, InfoMerge::Replace);
+            }
+            (_, _, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Replaces each `${VAR}` with the value of the environment variable. The
+/// shell doesn't usually export `HOSTNAME`, so it falls back to the kernel's.
+fn expand(path: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("Unclosed `${{` in include `{path}`."));
+        };
+        let name = &rest[start + 2..start + end];
+        let value = env::var(name).or_else(|_| match name {
+            "HOSTNAME" => system::hostname().map_err(|_| ()),
+            _ => Err(()),
+        });
+        let Ok(value) = value else {
+            return Err(format!(
+                "Environment variable `{name}` in include `{path}` is not set."
+            ));
+        };
+        out.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new directory with the given files in it.
+    fn files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("dyn-fetch-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            fs::write(dir.join(file), source).unwrap();
+        }
+        dir
+    }
+
+    fn load(path: &Path) -> io::Result<(Table, Vec<PathBuf>)> {
+        let source = fs::read_to_string(path)?;
+        let mut stack = vec![path.canonicalize()?];
+        resolve(parse_table(&source, path)?, path, &mut stack)
+    }
+
+    /// The `text` of every `info` entry, with the file it came from.
+    fn info(dir: &Path, top: &str) -> Vec<(String, String)> {
+        let (table, origins) = load(&dir.join(top)).unwrap();
+        let Some(Value::Array(info)) = table.get("info") else {
+            panic!("no info");
+        };
+        assert_eq!(info.len(), origins.len());
+        info.iter()
+            .zip(origins)
+            .map(|(entry, origin)| {
+                let text = entry["text"].as_str().unwrap().to_string();
+                let file = origin.file_name().unwrap().to_string_lossy().into_owned();
+                (text, file)
+            })
+            .collect()
+    }
+
+    fn entries(texts: &[&str]) -> String {
+        texts
+            .iter()
+            .map(|text| format!("[[info]]\ntype = \"const\"\ntext = \"{text}\"\n"))
+            .collect()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(text, file)| (text.to_string(), file.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn merges_info_as_info_merge_says() {
+        let base = entries(&["a", "b"]);
+        let top = |merge: &str| format!("include = \"base.toml\"\n{merge}\n{}", entries(&["c"]));
+        let dir = files(
+            "info-merge",
+            &[
+                ("base.toml", &base),
+                ("append.toml", &top("")),
+                ("prepend.toml", &top("info_merge = \"prepend\"")),
+                ("replace.toml", &top("info_merge = \"replace\"")),
+                (
+                    "empty.toml",
+                    "include = \"base.toml\"\ninfo_merge = \"replace\"",
+                ),
+            ],
+        );
+        assert_eq!(
+            info(&dir, "append.toml"),
+            pairs(&[("a", "base.toml"), ("b", "base.toml"), ("c", "append.toml")])
+        );
+        assert_eq!(
+            info(&dir, "prepend.toml"),
+            pairs(&[
+                ("c", "prepend.toml"),
+                ("a", "base.toml"),
+                ("b", "base.toml")
+            ])
+        );
+        assert_eq!(info(&dir, "replace.toml"), pairs(&[("c", "replace.toml")]));
+        // Replacing with no entries of its own keeps the included ones.
+        assert_eq!(
+            info(&dir, "empty.toml"),
+            pairs(&[("a", "base.toml"), ("b", "base.toml")])
+        );
+    }
+
+    #[test]
+    fn appends_includes_in_order() {
+        let dir = files(
+            "order",
+            &[
+                ("one.toml", &entries(&["a"])),
+                (
+                    "two.toml",
+                    &format!("include = \"one.toml\"\n{}", entries(&["b"])),
+                ),
+                (
+                    "top.toml",
+                    &format!(
+                        "include = [\"two.toml\", \"one.toml\"]\n{}",
+                        entries(&["c"])
+                    ),
+                ),
+            ],
+        );
+        assert_eq!(
+            info(&dir, "top.toml"),
+            pairs(&[
+                ("a", "one.toml"),
+                ("b", "two.toml"),
+                ("a", "one.toml"),
+                ("c", "top.toml")
+            ])
+        );
+    }
+
+    #[test]
+    fn merges_nested_tables_key_by_key() {
+        let mut base: Table =
+            "color = \"auto\"\n[art]\nwidth = 10\nheight = 5\n[art.colors]\nc1 = \"red\"\nc2 = \"blue\""
+                .parse()
+                .unwrap();
+        let over: Table = "color = \"never\"\n[art]\nwidth = 20\n[art.colors]\nc1 = \"green\""
+            .parse()
+            .unwrap();
+        merge(&mut base, over, InfoMerge::Append);
+        let expected: Table =
+            "color = \"never\"\n[art]\nwidth = 20\nheight = 5\n[art.colors]\nc1 = \"green\"\nc2 = \"blue\""
+                .parse()
+                .unwrap();
+        assert_eq!(base, expected);
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let dir = files(
+            "cycle",
+            &[
+                ("a.toml", "include = \"b.toml\""),
+                ("b.toml", "include = \"a.toml\""),
+            ],
+        );
+        let err = load(&dir.join("a.toml")).unwrap_err();
+        assert!(err.to_string().contains("includes itself"), "{err}");
+    }
+
+    #[test]
+    fn skips_only_optional_missing_includes() {
+        let dir = files(
+            "optional",
+            &[
+                ("required.toml", "include = \"missing.toml\""),
+                ("optional.toml", "include = \"missing.toml?\""),
+                ("host.toml", "include = \"missing-${HOSTNAME}.toml\""),
+            ],
+        );
+        let err = load(&dir.join("required.toml")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(load(&dir.join("optional.toml")).is_ok());
+        assert!(load(&dir.join("host.toml")).is_ok());
+    }
+
+    #[test]
+    fn expands_variables() {
+        let hostname = env::var("HOSTNAME")
+            .or_else(|_| system::hostname())
+            .unwrap();
+        assert_eq!(
+            expand("hosts/${HOSTNAME}.toml").unwrap(),
+            format!("hosts/{hostname}.toml")
+        );
+        assert_eq!(expand("plain.toml").unwrap(), "plain.toml");
+        assert!(expand("${HOSTNAME").is_err());
+        assert!(expand("${DYN_FETCH_TEST_UNSET_VARIABLE}").is_err());
+    }
+}
@@ -1,58 +1,244 @@
-use std::mem;
+use std::{
+    env,
+    io::{IsTerminal, stdout},
+    mem,
+    sync::atomic::{AtomicU8, Ordering},
+};
 
 use serde::{
     Deserialize,
-    de::{Unexpected, Visitor},
+    de::{MapAccess, SeqAccess, Unexpected, Visitor, value::MapAccessDeserializer},
 };
 
-#[derive(Deserialize, Clone, Copy, Debug)]
+use crate::width::display_width;
+
+static COLOR_LEVEL: AtomicU8 = AtomicU8::new(ColorLevel::TrueColor as u8);
+
+/// Which colors the terminal can show.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorLevel {
+    /// No escape codes at all.
+    None,
+    /// The 16 basic and bright colors.
+    Basic,
+    /// The 256 color palette.
+    Palette,
+    TrueColor,
+}
+
+impl ColorLevel {
+    /// Guesses what the terminal supports from `COLORTERM` and `TERM`.
+    fn from_env() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorLevel::TrueColor
+        } else if term.contains("256color") {
+            ColorLevel::Palette
+        } else if term == "dumb" {
+            ColorLevel::None
+        } else {
+            ColorLevel::Basic
+        }
+    }
+}
+
+/// Whether to use colors, from the `color` option.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorChoice {
+    /// Use colors when printing to a terminal, unless `NO_COLOR` is set or
+    /// `CLICOLOR_FORCE` is.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn level(self) -> ColorLevel {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let force =
+            env::var_os("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0");
+        match self {
+            ColorChoice::Never => ColorLevel::None,
+            ColorChoice::Always => ColorLevel::from_env().max(ColorLevel::Basic),
+            ColorChoice::Auto if no_color => ColorLevel::None,
+            ColorChoice::Auto if force => ColorLevel::from_env().max(ColorLevel::Basic),
+            ColorChoice::Auto if !stdout().is_terminal() => ColorLevel::None,
+            ColorChoice::Auto => ColorLevel::from_env(),
+        }
+    }
+}
+
+/// Sets which colors are used for everything formatted afterwards. Colors the
+/// terminal can't show are replaced with the closest one it can.
+pub fn set_color_level(level: ColorLevel) {
+    COLOR_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+fn color_level() -> ColorLevel {
+    match COLOR_LEVEL.load(Ordering::Relaxed) {
+        0 => ColorLevel::None,
+        1 => ColorLevel::Basic,
+        2 => ColorLevel::Palette,
+        _ => ColorLevel::TrueColor,
+    }
+}
+
+pub fn color_enabled() -> bool {
+    color_level() != ColorLevel::None
+}
+
+/// The escape code that clears any style started with [`Style::format_start`].
+pub fn reset() -> &'static str {
+    if color_enabled() { "\x1b[0m" } else { "" }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
 pub struct Style {
     #[serde(default)]
-    fg: Color,
+    pub fg: Color,
+    #[serde(default)]
+    pub bg: Color,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub dim: bool,
+    #[serde(default)]
+    pub underline: Underline,
+    #[serde(default)]
+    pub underline_color: Color,
     #[serde(default)]
-    bg: Color,
+    pub strikethrough: bool,
     #[serde(default)]
-    bold: bool,
+    pub blink: bool,
+    /// Swaps the foreground and background.
     #[serde(default)]
-    italic: bool,
+    pub reverse: bool,
     #[serde(default)]
-    dim: bool,
+    pub hidden: bool,
+    #[serde(default)]
+    pub overline: bool,
 }
 
 impl Style {
-    fn codes(self) -> Vec<u8> {
-        let mut codes = Vec::new();
-        self.fg.append_foreground(&mut codes);
-        self.bg.append_background(&mut codes);
-        if self.bold {
-            codes.push(1);
-        }
-        if self.italic {
-            codes.push(3);
-        }
-        if self.dim {
-            codes.push(2);
+    fn codes(self) -> Vec<String> {
+        let mut colors = Vec::new();
+        self.fg.append_foreground(&mut colors);
+        self.bg.append_background(&mut colors);
+        let mut codes: Vec<String> = colors.iter().map(u8::to_string).collect();
+        let attributes = [
+            (self.bold, "1"),
+            (self.italic, "3"),
+            (self.dim, "2"),
+            (self.blink, "5"),
+            (self.reverse, "7"),
+            (self.hidden, "8"),
+            (self.strikethrough, "9"),
+            (self.overline, "53"),
+        ];
+        codes.extend(
+            attributes
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, code)| code.to_string()),
+        );
+        if let Some(code) = self.underline.code() {
+            codes.push(code.to_string());
         }
+        let mut underline_color = Vec::new();
+        self.underline_color.append_underline(&mut underline_color);
+        codes.extend(underline_color.iter().map(u8::to_string));
         codes
     }
 
     pub fn format_start(self) -> String {
-        format!(
-            "\x1b[{}m",
-            self.codes()
-                .iter()
-                .map(|c| c.to_string() + ";")
-                .collect::<String>()
-        )
+        if !color_enabled() {
+            return String::new();
+        }
+        format!("\x1b[{}m", self.codes().join(";"))
     }
     pub fn format(self, text: &str) -> String {
-        format!(
-            "\x1b[{}m{text}\x1b[0m",
-            self.codes()
-                .iter()
-                .map(|c| c.to_string() + ";")
-                .collect::<String>()
-        )
+        if !color_enabled() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{text}\x1b[0m", self.codes().join(";"))
+    }
+}
+
+/// How text is underlined. `true` and `false` can be used for a single line
+/// and none.
+#[derive(Default, Clone, Copy, Debug)]
+pub enum Underline {
+    #[default]
+    None,
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl Underline {
+    /// The SGR code, where styles other than a single line use the `4:x` form.
+    fn code(self) -> Option<&'static str> {
+        match self {
+            Underline::None => None,
+            Underline::Single => Some("4"),
+            Underline::Double => Some("4:2"),
+            Underline::Curly => Some("4:3"),
+            Underline::Dotted => Some("4:4"),
+            Underline::Dashed => Some("4:5"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Underline {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(UnderlineVisitor)
+    }
+}
+
+struct UnderlineVisitor;
+impl Visitor<'_> for UnderlineVisitor {
+    type Value = Underline;
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a boolean or an underline style")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(if value {
+            Underline::Single
+        } else {
+            Underline::None
+        })
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        match value {
+            "none" | "false" => Ok(Underline::None),
+            "single" | "true" => Ok(Underline::Single),
+            "double" => Ok(Underline::Double),
+            "curly" => Ok(Underline::Curly),
+            "dotted" => Ok(Underline::Dotted),
+            "dashed" => Ok(Underline::Dashed),
+            _ => Err(E::unknown_variant(
+                value,
+                &["none", "single", "double", "curly", "dotted", "dashed"],
+            )),
+        }
     }
 }
 
@@ -66,8 +252,11 @@ pub fn option_format(style: Option<Style>, text: &str) -> String {
 
 #[derive(Default, Clone, Copy, Debug)]
 pub enum Color {
+    /// Leaves the color as it is.
     #[default]
     Default,
+    /// Switches back to the terminal's own color.
+    Reset,
     Red,
     Green,
     Yellow,
@@ -76,13 +265,45 @@ pub enum Color {
     Cyan,
     White,
     Black,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+    BrightBlack,
+    /// A color from the 256 color palette.
+    Indexed(u8),
     Hex(u8, u8, u8),
 }
 
+const COLOR_NAMES: &[&str] = &[
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+    "bright_black",
+    "default",
+    "reset",
+];
+
 impl Color {
+    /// The foreground code of a color that has one.
     fn ansi_code(&self) -> u8 {
         match self {
-            Color::Default => 39,
+            Color::Default | Color::Reset => 39,
             Color::Red => 31,
             Color::Green => 32,
             Color::Yellow => 33,
@@ -91,31 +312,136 @@ impl Color {
             Color::Cyan => 36,
             Color::White => 37,
             Color::Black => 30,
-            Color::Hex(_, _, _) => panic!("hex doesn't have a numeric code."),
+            Color::BrightRed => 91,
+            Color::BrightGreen => 92,
+            Color::BrightYellow => 93,
+            Color::BrightBlue => 94,
+            Color::BrightMagenta => 95,
+            Color::BrightCyan => 96,
+            Color::BrightWhite => 97,
+            Color::BrightBlack => 90,
+            Color::Indexed(_) | Color::Hex(_, _, _) => {
+                panic!("indexed and hex colors don't have a single code.")
+            }
+        }
+    }
+    /// The closest color the terminal can show.
+    fn downsample(self) -> Color {
+        match (self, color_level()) {
+            (Color::Hex(r, g, b), ColorLevel::Palette) => Color::Indexed(palette_index(r, g, b)),
+            (Color::Hex(r, g, b), ColorLevel::Basic | ColorLevel::None) => basic(r, g, b),
+            (Color::Indexed(n), ColorLevel::Basic | ColorLevel::None) => {
+                let (r, g, b) = palette_rgb(n);
+                basic(r, g, b)
+            }
+            (color, _) => color,
         }
     }
     pub fn append_foreground(self, vec: &mut Vec<u8>) {
-        match self {
+        match self.downsample() {
             Color::Default => (),
-            Color::Hex(r, g, b) => {
-                vec.push(38);
-                vec.push(r);
-                vec.push(g);
-                vec.push(b);
+            Color::Indexed(n) => vec.extend([38, 5, n]),
+            Color::Hex(r, g, b) => vec.extend([38, 2, r, g, b]),
+            color => vec.push(color.ansi_code()),
+        }
+    }
+    /// Appends the underline color, which uses palette indexes for basic colors
+    /// since there are no codes for them.
+    pub fn append_underline(self, vec: &mut Vec<u8>) {
+        match self.downsample() {
+            Color::Default => (),
+            Color::Reset => vec.push(59),
+            Color::Indexed(n) => vec.extend([58, 5, n]),
+            Color::Hex(r, g, b) => vec.extend([58, 2, r, g, b]),
+            color => {
+                let code = color.ansi_code();
+                let index = if code >= 90 { code - 82 } else { code - 30 };
+                vec.extend([58, 5, index]);
             }
-            _ => vec.push(self.ansi_code()),
         }
     }
     pub fn append_background(self, vec: &mut Vec<u8>) {
-        match self {
+        match self.downsample() {
             Color::Default => (),
-            Color::Hex(r, g, b) => {
-                vec.push(48);
-                vec.push(r);
-                vec.push(g);
-                vec.push(b);
-            }
-            _ => vec.push(self.ansi_code() + 10),
+            Color::Indexed(n) => vec.extend([48, 5, n]),
+            Color::Hex(r, g, b) => vec.extend([48, 2, r, g, b]),
+            color => vec.push(color.ansi_code() + 10),
+        }
+    }
+}
+
+/// The basic colors as xterm shows them, in palette order.
+const BASIC: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::White, (229, 229, 229)),
+    (Color::BrightBlack, (127, 127, 127)),
+    (Color::BrightRed, (255, 0, 0)),
+    (Color::BrightGreen, (0, 255, 0)),
+    (Color::BrightYellow, (255, 255, 0)),
+    (Color::BrightBlue, (92, 92, 255)),
+    (Color::BrightMagenta, (255, 0, 255)),
+    (Color::BrightCyan, (0, 255, 255)),
+    (Color::BrightWhite, (255, 255, 255)),
+];
+
+/// The levels of each channel in the 6×6×6 color cube of the palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The closest basic color.
+fn basic(r: u8, g: u8, b: u8) -> Color {
+    BASIC
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map(|(color, _)| *color)
+        .expect("there are basic colors")
+}
+
+/// The closest color in the cube or the gray ramp of the 256 color palette.
+fn palette_index(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| level.abs_diff(c))
+            .map(|(i, _)| i as u8)
+            .expect("there are cube levels")
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+    let gray = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = 232 + (gray.saturating_sub(3) / 10).min(23) as u8;
+    if distance(palette_rgb(gray), (r, g, b)) < distance(palette_rgb(cube), (r, g, b)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// The color at `n` in the 256 color palette.
+fn palette_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..16 => BASIC[n as usize].1,
+        16..232 => {
+            let n = n - 16;
+            (
+                CUBE_LEVELS[(n / 36) as usize],
+                CUBE_LEVELS[(n / 6 % 6) as usize],
+                CUBE_LEVELS[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + 10 * (n - 232);
+            (gray, gray, gray)
         }
     }
 }
@@ -140,29 +466,72 @@ impl Visitor<'_> for ColorVisitor {
     where
         E: serde::de::Error,
     {
-        if value.starts_with("#") {
-            let Ok(r): Result<u8, _> = value[1..=2].parse() else {
-                return Err(E::invalid_value(
-                    Unexpected::Str(&value[1..=2]),
-                    &"a hexidecimal number",
-                ));
+        if let Some(hex) = value.strip_prefix('#') {
+            let digits = match hex.len() {
+                3 | 6 if hex.is_ascii() => hex.len() / 3,
+                _ => {
+                    return Err(E::invalid_value(
+                        Unexpected::Str(value),
+                        &"a color like `#rgb` or `#rrggbb`",
+                    ));
+                }
             };
-            let Ok(g): Result<u8, _> = value[3..=4].parse() else {
-                return Err(E::invalid_value(
-                    Unexpected::Str(&value[1..=2]),
-                    &"a hexidecimal number",
-                ));
+            let mut rgb = [0; 3];
+            for (i, channel) in rgb.iter_mut().enumerate() {
+                let component = &hex[i * digits..(i + 1) * digits];
+                if !component.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(E::invalid_value(
+                        Unexpected::Str(component),
+                        &"a hexadecimal number",
+                    ));
+                }
+                *channel = u8::from_str_radix(component, 16).expect("hex digits are valid");
+                // Each digit of `#rgb` stands for the same digit twice.
+                if digits == 1 {
+                    *channel *= 17;
+                }
+            }
+            Ok(Color::Hex(rgb[0], rgb[1], rgb[2]))
+        } else if let Some(args) = function(value, "rgb") {
+            let [r, g, b] = components(value, args)?;
+            let component = |component: &str| {
+                component.parse::<u8>().map_err(|_| {
+                    E::invalid_value(Unexpected::Str(component), &"a number from 0 to 255")
+                })
             };
-            let Ok(b): Result<u8, _> = value[5..=6].parse() else {
-                return Err(E::invalid_value(
-                    Unexpected::Str(&value[1..=2]),
-                    &"a hexidecimal number",
-                ));
+            Ok(Color::Hex(component(r)?, component(g)?, component(b)?))
+        } else if let Some(index) = function(value, "color") {
+            let index = index.trim();
+            index.parse().map(Color::Indexed).map_err(|_| {
+                E::invalid_value(Unexpected::Str(index), &"a palette index from 0 to 255")
+            })
+        } else if let Some(args) = function(value, "hsl") {
+            let [h, s, l] = components(value, args)?;
+            let hue = h
+                .strip_suffix("deg")
+                .unwrap_or(h)
+                .parse::<f64>()
+                .ok()
+                .filter(|h| h.is_finite())
+                .ok_or_else(|| E::invalid_value(Unexpected::Str(h), &"a hue in degrees"))?;
+            let percent = |component: &str| {
+                component
+                    .strip_suffix('%')
+                    .unwrap_or(component)
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|p| (0.0..=100.0).contains(p))
+                    .map(|p| p / 100.0)
+                    .ok_or_else(|| {
+                        E::invalid_value(Unexpected::Str(component), &"a percentage from 0 to 100")
+                    })
             };
+            let (r, g, b) = hsl_to_rgb(hue, percent(s)?, percent(l)?);
             Ok(Color::Hex(r, g, b))
         } else {
             match value {
                 "default" => Ok(Color::Default),
+                "reset" => Ok(Color::Reset),
                 "red" => Ok(Color::Red),
                 "green" => Ok(Color::Green),
                 "yellow" => Ok(Color::Yellow),
@@ -171,31 +540,131 @@ impl Visitor<'_> for ColorVisitor {
                 "cyan" => Ok(Color::Cyan),
                 "white" => Ok(Color::White),
                 "black" => Ok(Color::Black),
-                _ => Err(E::unknown_variant(
-                    value,
-                    &[
-                        "red", "green", "yellow", "blue", "magenta", "cyan", "white", "black",
-                        "default",
-                    ],
-                )),
+                "bright_red" => Ok(Color::BrightRed),
+                "bright_green" => Ok(Color::BrightGreen),
+                "bright_yellow" => Ok(Color::BrightYellow),
+                "bright_blue" => Ok(Color::BrightBlue),
+                "bright_magenta" => Ok(Color::BrightMagenta),
+                "bright_cyan" => Ok(Color::BrightCyan),
+                "bright_white" => Ok(Color::BrightWhite),
+                "bright_black" => Ok(Color::BrightBlack),
+                _ => Err(E::unknown_variant(value, COLOR_NAMES)),
             }
         }
     }
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+/// The arguments of `name(...)` in `value`, if it is a call to `name`.
+fn function<'a>(value: &'a str, name: &str) -> Option<&'a str> {
+    value
+        .strip_prefix(name)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+/// Splits the arguments of a color function into its three components.
+fn components<'a, E: serde::de::Error>(value: &str, args: &'a str) -> Result<[&'a str; 3], E> {
+    let components: Vec<_> = args.split(',').map(str::trim).collect();
+    components.try_into().map_err(|components: Vec<_>| {
+        E::invalid_length(
+            components.len(),
+            &format!("three components in `{value}`").as_str(),
+        )
+    })
+}
+
+/// Converts a hue in degrees and a saturation and lightness from 0 to 1.
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (u8, u8, u8) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |c: f64| ((c + m) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+#[derive(Clone, Debug)]
 pub enum Text {
     Styled {
         text: String,
-        #[serde(flatten)]
         style: Style,
+        /// A URL that the text links to.
+        link: Option<String>,
     },
     Unstyled(String),
     Combine(Vec<Text>),
     Empty,
 }
 
+/// Text is a string, a table with `text` and a style, or an array of those. It
+/// isn't an untagged enum so that mistakes in a style, like a bad color, are
+/// reported as they are instead of as text that matched nothing.
+impl<'de> Deserialize<'de> for Text {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(TextVisitor)
+    }
+}
+
+struct TextVisitor;
+impl<'de> Visitor<'de> for TextVisitor {
+    type Value = Text;
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a string, a table with `text` and a style, or an array of those")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Text::Unstyled(value.to_string()))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Text::Empty)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut texts = Vec::new();
+        while let Some(text) = seq.next_element()? {
+            texts.push(text);
+        }
+        Ok(Text::Combine(texts))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Styled {
+            text: String,
+            #[serde(flatten)]
+            style: Style,
+            link: Option<String>,
+        }
+        let Styled { text, style, link } = Styled::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(Text::Styled { text, style, link })
+    }
+}
+
 impl Text {
     pub fn append(&mut self, v: Text) {
         let vec = match self {
@@ -216,23 +685,53 @@ impl Text {
             _ => vec.push(v),
         }
     }
+    /// Makes all of the text link to `url`.
+    pub fn with_link(self, url: &str) -> Text {
+        match self {
+            Text::Styled { text, style, .. } => Text::Styled {
+                text,
+                style,
+                link: Some(url.to_string()),
+            },
+            Text::Unstyled(text) => Text::Styled {
+                text,
+                style: Style::default(),
+                link: Some(url.to_string()),
+            },
+            Text::Combine(texts) => {
+                Text::Combine(texts.into_iter().map(|text| text.with_link(url)).collect())
+            }
+            Text::Empty => Text::Empty,
+        }
+    }
+    /// The number of terminal columns the text takes up.
     pub fn len(&self) -> usize {
         match self {
-            Text::Styled { text, style: _ } => text.chars().count(),
-            Text::Unstyled(text) => text.chars().count(),
+            Text::Styled { text, .. } => display_width(text),
+            Text::Unstyled(text) => display_width(text),
             Text::Combine(vec) => vec.iter().map(Self::len).sum(),
             Text::Empty => 0,
         }
     }
 }
 
-impl Into<String> for Text {
-    fn into(self) -> String {
-        match self {
-            Self::Styled { text, style } => style.format(&text),
-            Self::Unstyled(s) => s,
-            Self::Combine(v) => v.into_iter().map(Into::<String>::into).collect(),
-            Self::Empty => String::new(),
+impl From<Text> for String {
+    fn from(text: Text) -> String {
+        match text {
+            Text::Styled { text, style, link } => {
+                let text = style.format(&text);
+                match link {
+                    // An OSC 8 hyperlink, which terminals that don't know it
+                    // leave out.
+                    Some(link) if color_enabled() => {
+                        format!("\x1b]8;;{link}\x1b\\{text}\x1b]8;;\x1b\\")
+                    }
+                    _ => text,
+                }
+            }
+            Text::Unstyled(s) => s,
+            Text::Combine(v) => v.into_iter().map(Into::<String>::into).collect(),
+            Text::Empty => String::new(),
         }
     }
 }
@@ -242,3 +741,85 @@ impl Default for Text {
         Self::Unstyled(String::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::{IntoDeserializer, value::Error};
+
+    fn parse(value: &str) -> Result<Color, Error> {
+        Color::deserialize(value.into_deserializer())
+    }
+
+    fn rgb(value: &str) -> (u8, u8, u8) {
+        match parse(value) {
+            Ok(Color::Hex(r, g, b)) => (r, g, b),
+            color => panic!("`{value}` parsed as {color:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(rgb("#ff8000"), (255, 128, 0));
+        assert_eq!(rgb("#FF8000"), (255, 128, 0));
+        assert_eq!(rgb("#f80"), (255, 136, 0));
+        assert_eq!(rgb("#000"), (0, 0, 0));
+        for value in ["#", "#ff", "#ff80", "#ff800", "#gg0000", "#ff80000", "#é0"] {
+            assert!(parse(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn parses_rgb_colors() {
+        assert_eq!(rgb("rgb(255, 128, 0)"), (255, 128, 0));
+        assert_eq!(rgb("rgb(0,0,0)"), (0, 0, 0));
+        for value in [
+            "rgb(256, 0, 0)",
+            "rgb(-1, 0, 0)",
+            "rgb(0, 0)",
+            "rgb(0, 0, 0, 0)",
+        ] {
+            assert!(parse(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn parses_hsl_colors() {
+        assert_eq!(rgb("hsl(120, 100%, 50%)"), (0, 255, 0));
+        assert_eq!(rgb("hsl(240deg, 100%, 25%)"), (0, 0, 128));
+        for value in ["hsl(0, 101%, 50%)", "hsl(0, 100%, -1%)", "hsl(red, 1%, 1%)"] {
+            assert!(parse(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn parses_palette_indexes_and_names() {
+        assert!(matches!(parse("color(42)"), Ok(Color::Indexed(42))));
+        assert!(parse("color(256)").is_err());
+        assert!(matches!(parse("bright_red"), Ok(Color::BrightRed)));
+        assert!(parse("redish").is_err());
+    }
+
+    #[test]
+    fn converts_hsl_to_rgb() {
+        assert_eq!(hsl_to_rgb(0.0, 1.0, 0.5), (255, 0, 0));
+        assert_eq!(hsl_to_rgb(360.0, 1.0, 0.5), (255, 0, 0));
+        assert_eq!(hsl_to_rgb(-120.0, 1.0, 0.5), (0, 0, 255));
+        assert_eq!(hsl_to_rgb(60.0, 1.0, 0.5), (255, 255, 0));
+        assert_eq!(hsl_to_rgb(0.0, 0.0, 0.5), (128, 128, 128));
+        assert_eq!(hsl_to_rgb(0.0, 1.0, 1.0), (255, 255, 255));
+    }
+
+    #[test]
+    fn finds_the_closest_palette_index() {
+        assert_eq!(palette_index(255, 0, 0), 196);
+        assert_eq!(palette_index(0, 0, 0), 16);
+        assert_eq!(palette_index(255, 255, 255), 231);
+        assert_eq!(palette_index(128, 128, 128), 244);
+        assert_eq!(palette_index(8, 8, 8), 232);
+        for n in 16..=255 {
+            let (r, g, b) = palette_rgb(n);
+            assert_eq!(palette_rgb(palette_index(r, g, b)), (r, g, b), "{n}");
+        }
+    }
+}
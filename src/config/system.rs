@@ -0,0 +1,458 @@
+use std::{
+    collections::HashSet,
+    env::{self, consts::ARCH},
+    ffi::{CStr, CString},
+    fs, io,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use super::format::Fields;
+
+fn read(path: impl AsRef<Path>) -> io::Result<String> {
+    let path = path.as_ref();
+    fs::read_to_string(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Could not read `{}`: {err}", path.display()),
+        )
+    })
+}
+
+fn parse_error(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Could not parse `{path}`."),
+    )
+}
+
+/// Parses a `KEY=value` file like `/etc/os-release`, removing quotes around
+/// values.
+pub fn os_release() -> io::Result<Vec<(String, String)>> {
+    let file = read("/etc/os-release").or_else(|_| read("/usr/lib/os-release"))?;
+    Ok(file
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.trim().to_string(),
+                value.trim().trim_matches(['"', '\'']).to_string(),
+            )
+        })
+        .collect())
+}
+
+pub fn os() -> io::Result<Fields> {
+    let release = os_release()?;
+    let get = |key: &str| {
+        release
+            .iter()
+            .find(|(k, _)| k == key)
+            .map_or("", |(_, v)| v.as_str())
+    };
+    let name = if get("NAME").is_empty() {
+        "Linux"
+    } else {
+        get("NAME")
+    };
+    let pretty_name = if get("PRETTY_NAME").is_empty() {
+        name
+    } else {
+        get("PRETTY_NAME")
+    };
+    Ok(Fields::new("{pretty_name}")
+        .with("name", name)
+        .with("pretty_name", pretty_name)
+        .with("id", get("ID"))
+        .with("version", get("VERSION_ID"))
+        .with("codename", get("VERSION_CODENAME"))
+        .with("arch", ARCH))
+}
+
+pub fn kernel() -> io::Result<Fields> {
+    Ok(Fields::new("{release}")
+        .with("name", read("/proc/sys/kernel/ostype")?.trim())
+        .with("release", read("/proc/sys/kernel/osrelease")?.trim())
+        .with("version", read("/proc/sys/kernel/version")?.trim())
+        .with("arch", ARCH))
+}
+
+/// The hostname from the kernel. The shell doesn't usually export `HOSTNAME`,
+/// so this is read instead.
+pub fn hostname() -> io::Result<String> {
+    Ok(read("/proc/sys/kernel/hostname")?.trim().to_string())
+}
+
+/// The name of the user the program runs as, like `whoami` prints, or `$USER`
+/// if it isn't in the user database.
+pub fn username() -> io::Result<String> {
+    let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+    let mut buf = vec![0; 4096];
+    let mut result = ptr::null_mut();
+    // SAFETY: `buf` outlives the `passwd` that points into it, and the name is
+    // only read if `getpwuid_r` reports that it found the user.
+    unsafe {
+        libc::getpwuid_r(
+            libc::geteuid(),
+            passwd.as_mut_ptr(),
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        );
+        if !result.is_null() {
+            return Ok(CStr::from_ptr((*result).pw_name)
+                .to_string_lossy()
+                .into_owned());
+        }
+    }
+    env::var("USER").map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not find the name of the current user.",
+        )
+    })
+}
+
+pub fn uptime() -> io::Result<Fields> {
+    let file = read("/proc/uptime")?;
+    let total = file
+        .split_whitespace()
+        .next()
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or_else(|| parse_error("/proc/uptime"))? as u64;
+    let (days, hours, minutes, seconds) = (
+        total / 86400,
+        total % 86400 / 3600,
+        total % 3600 / 60,
+        total % 60,
+    );
+    let mut pretty = Vec::new();
+    if days > 0 {
+        pretty.push(format!("{days}d"));
+    }
+    if hours > 0 {
+        pretty.push(format!("{hours}h"));
+    }
+    if minutes > 0 || pretty.is_empty() {
+        pretty.push(format!("{minutes}m"));
+    }
+    Ok(Fields::new("{pretty}")
+        .with("pretty", pretty.join(" "))
+        .with("days", days)
+        .with("hours", hours)
+        .with("minutes", minutes)
+        .with("seconds", seconds)
+        .with("total_seconds", total))
+}
+
+pub fn cpu() -> io::Result<Fields> {
+    let file = read("/proc/cpuinfo")?;
+    let field = |key: &str| {
+        file.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim())
+    };
+    let model = field("model name")
+        .or_else(|| field("Hardware"))
+        .or_else(|| field("cpu model"))
+        .unwrap_or("Unknown CPU");
+    let threads = file
+        .lines()
+        .filter(|line| line.starts_with("processor"))
+        .count();
+    let cores = field("cpu cores")
+        .and_then(|c| c.parse::<usize>().ok())
+        .unwrap_or(threads);
+    let mhz = field("cpu MHz")
+        .and_then(|f| f.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    Ok(Fields::new("{model} ({threads})")
+        .with("model", model)
+        .with("cores", cores)
+        .with("threads", threads)
+        .with("mhz", mhz.round())
+        .with("ghz", format!("{:.2}", mhz / 1000.0)))
+}
+
+fn meminfo() -> io::Result<Vec<(String, u64)>> {
+    Ok(read("/proc/meminfo")?
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            let kib = value.split_whitespace().next()?.parse().ok()?;
+            Some((key.to_string(), kib))
+        })
+        .collect())
+}
+
+/// Sizes are given in MiB, with `percent` being the share of `total` in use.
+fn usage(default_format: &'static str, used_kib: u64, total_kib: u64) -> Fields {
+    let percent = (used_kib * 100).checked_div(total_kib).unwrap_or(0);
+    Fields::new(default_format)
+        .with("used", used_kib / 1024)
+        .with("total", total_kib / 1024)
+        .with("free", total_kib.saturating_sub(used_kib) / 1024)
+        .with("used_gib", format!("{:.2}", used_kib as f64 / 1048576.0))
+        .with("total_gib", format!("{:.2}", total_kib as f64 / 1048576.0))
+        .with("percent", percent)
+}
+
+pub fn memory() -> io::Result<Fields> {
+    let info = meminfo()?;
+    let get = |key: &str| {
+        info.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| parse_error("/proc/meminfo"))
+    };
+    let total = get("MemTotal")?;
+    let available = get("MemAvailable").or_else(|_| get("MemFree"))?;
+    Ok(usage(
+        "{used}/{total} MiB ({percent}%)",
+        total.saturating_sub(available),
+        total,
+    ))
+}
+
+pub fn swap() -> io::Result<Fields> {
+    let info = meminfo()?;
+    let get = |key: &str| {
+        info.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| parse_error("/proc/meminfo"))
+    };
+    let total = get("SwapTotal")?;
+    let free = get("SwapFree")?;
+    Ok(usage(
+        "{used}/{total} MiB ({percent}%)",
+        total.saturating_sub(free),
+        total,
+    ))
+}
+
+pub fn load_average() -> io::Result<Fields> {
+    let file = read("/proc/loadavg")?;
+    let mut loads = file.split_whitespace();
+    let mut next = || loads.next().ok_or_else(|| parse_error("/proc/loadavg"));
+    Ok(Fields::new("{one}, {five}, {fifteen}")
+        .with("one", next()?)
+        .with("five", next()?)
+        .with("fifteen", next()?))
+}
+
+pub fn disk(mount: &str) -> io::Result<Fields> {
+    let path = CString::new(Path::new(mount).as_os_str().as_bytes())
+        .map_err(|_| io::Error::other(format!("Invalid mount point `{mount}`.")))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read after
+    // `statvfs` reports that it filled it in.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Could not read disk usage of `{mount}`: {err}"),
+            ));
+        }
+        stat.assume_init()
+    };
+    let block = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block / 1024;
+    let free = stat.f_bfree as u64 * block / 1024;
+    let available = stat.f_bavail as u64 * block / 1024;
+    // Like `df`, the share in use ignores blocks reserved for root.
+    let used = total.saturating_sub(free);
+    let percent = (used * 100).checked_div(used + available).unwrap_or(0);
+    Ok(Fields::new("{used_gib}/{total_gib} GiB ({percent}%)")
+        .with("mount", mount)
+        .with("used", used / 1024)
+        .with("total", total / 1024)
+        .with("free", available / 1024)
+        .with("used_gib", format!("{:.1}", used as f64 / 1048576.0))
+        .with("total_gib", format!("{:.1}", total as f64 / 1048576.0))
+        .with("free_gib", format!("{:.1}", available as f64 / 1048576.0))
+        .with("percent", percent))
+}
+
+pub fn battery(name: Option<&str>) -> io::Result<Fields> {
+    let dir = match name {
+        Some(name) => Path::new("/sys/class/power_supply").join(name),
+        None => fs::read_dir("/sys/class/power_supply")?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| fs::read_to_string(path.join("type")).is_ok_and(|t| t.trim() == "Battery"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No battery was found."))?,
+    };
+    let capacity = read(dir.join("capacity"))?;
+    let status = read(dir.join("status")).unwrap_or_else(|_| String::from("Unknown"));
+    Ok(Fields::new("{capacity}% ({status})")
+        .with(
+            "name",
+            dir.file_name().unwrap_or_default().to_string_lossy(),
+        )
+        .with("capacity", capacity.trim())
+        .with("status", status.trim()))
+}
+
+fn count_dirs(path: &str) -> usize {
+    fs::read_dir(path).map_or(0, |dir| {
+        dir.filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .count()
+    })
+}
+
+fn count_lines(path: &str, prefix: &str) -> usize {
+    fs::read_to_string(path).map_or(0, |file| {
+        file.lines().filter(|line| line.starts_with(prefix)).count()
+    })
+}
+
+/// Counts the packages in a Nix profile from its manifest. Profiles that NixOS
+/// and home-manager build have none, so the packages that put programs in the
+/// profile's `bin` are counted instead.
+fn count_nix_profile(profile: &Path) -> usize {
+    if let Ok(manifest) = fs::read_to_string(profile.join("manifest.json")) {
+        // Made by `nix profile`.
+        return manifest.matches("\"storePaths\"").count();
+    }
+    if let Ok(manifest) = fs::read_to_string(profile.join("manifest.nix")) {
+        // Made by `nix-env`.
+        return manifest.matches("type = \"derivation\"").count();
+    }
+    let Ok(bin) = fs::read_dir(profile.join("bin")) else {
+        return 0;
+    };
+    let store_paths: HashSet<_> = bin
+        .filter_map(Result::ok)
+        .filter_map(|entry| fs::read_link(entry.path()).ok())
+        .filter_map(|target| {
+            let store_path = target
+                .strip_prefix("/nix/store")
+                .ok()?
+                .components()
+                .next()?;
+            Some(store_path.as_os_str().to_owned())
+        })
+        .collect();
+    store_paths.len()
+}
+
+/// Counts the packages in the user's, the default and the NixOS system
+/// profiles, which are often links to one another.
+fn count_nix() -> usize {
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    let mut profiles = vec![
+        home.join(".nix-profile"),
+        home.join(".local/state/nix/profile"),
+        PathBuf::from("/nix/var/nix/profiles/default"),
+        PathBuf::from("/run/current-system/sw"),
+    ];
+    if let Ok(user) = username() {
+        profiles.push(Path::new("/etc/profiles/per-user").join(user));
+    }
+    let profiles: HashSet<_> = profiles
+        .iter()
+        .filter_map(|profile| profile.canonicalize().ok())
+        .collect();
+    profiles
+        .iter()
+        .map(|profile| count_nix_profile(profile))
+        .sum()
+}
+
+/// Counts installed packages by reading each package manager's database.
+pub fn packages() -> io::Result<Fields> {
+    let dpkg = fs::read_to_string("/var/lib/dpkg/status").map_or(0, |file| {
+        file.split("\n\n")
+            .filter(|package| package.contains("Status: install ok installed"))
+            .count()
+    });
+    let managers = [
+        ("dpkg", dpkg),
+        ("pacman", count_dirs("/var/lib/pacman/local")),
+        ("apk", count_lines("/lib/apk/db/installed", "P:")),
+        ("flatpak", count_dirs("/var/lib/flatpak/app")),
+        // `/snap/bin` holds the launchers rather than a package.
+        ("snap", count_dirs("/snap").saturating_sub(1)),
+        ("nix", count_nix()),
+    ];
+    let total: usize = managers.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        let names: Vec<_> = managers.iter().map(|(name, _)| *name).collect();
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "No packages were found for any supported package manager: {}.",
+                names.join(", ")
+            ),
+        ));
+    }
+    let summary = managers
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(name, count)| format!("{count} ({name})"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut fields = Fields::new("{summary}")
+        .with("total", total)
+        .with("summary", summary);
+    for (name, count) in managers {
+        fields = fields.with(name, count);
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn profile(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("dyn-fetch-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn counts_nix_profile_manifests() {
+        let dir = profile("nix-profile");
+        fs::write(
+            dir.join("manifest.json"),
+            r#"{"elements":{"hello":{"active":true,"storePaths":["/nix/store/a-hello"]},
+            "git":{"active":true,"storePaths":["/nix/store/b-git"]}},"version":3}"#,
+        )
+        .unwrap();
+        assert_eq!(count_nix_profile(&dir), 2);
+
+        let dir = profile("nix-env");
+        fs::write(
+            dir.join("manifest.nix"),
+            r#"[ { meta = { }; name = "hello-2.12"; out = { outPath = "/nix/store/a-hello"; };
+            outPath = "/nix/store/a-hello"; outputs = [ "out" ]; type = "derivation"; } ]"#,
+        )
+        .unwrap();
+        assert_eq!(count_nix_profile(&dir), 1);
+    }
+
+    #[test]
+    fn counts_nix_profiles_without_a_manifest_by_their_programs() {
+        let dir = profile("nixos");
+        fs::create_dir(dir.join("bin")).unwrap();
+        for (program, target) in [
+            ("git", "/nix/store/a-git/bin/git"),
+            ("git-shell", "/nix/store/a-git/bin/git-shell"),
+            ("hello", "/nix/store/b-hello/bin/hello"),
+            ("local", "/usr/bin/local"),
+        ] {
+            symlink(target, dir.join("bin").join(program)).unwrap();
+        }
+        assert_eq!(count_nix_profile(&dir), 2);
+        assert_eq!(count_nix_profile(&dir.join("missing")), 0);
+    }
+}
@@ -1,10 +1,16 @@
-use std::io::{self, Write, stdout};
+use std::{
+    io::{self, Write},
+    thread,
+};
 
 use serde::Deserialize;
 
-use crate::config::{
-    Config, Info,
-    style::{Style, Text, option_format},
+use crate::{
+    config::{
+        Info, OnError, default_error_text,
+        style::{Style, Text, option_format, reset},
+    },
+    width::clusters,
 };
 
 #[derive(Deserialize)]
@@ -13,87 +19,426 @@ pub enum Layout {
     Rectangle {
         round_corners: bool,
         border_style: Option<Style>,
+        /// The widest the box may be, borders included.
+        max_width: Option<usize>,
+        #[serde(default)]
+        overflow: Overflow,
     },
     Table {
         round_corners: bool,
         border_style: Option<Style>,
+        /// The widest the box may be, borders included.
+        max_width: Option<usize>,
+        #[serde(default)]
+        overflow: Overflow,
     },
 }
 
+/// What to do with text that is wider than it may be.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// Cut it off.
+    Truncate,
+    /// Cut it off and end it with `…`.
+    #[default]
+    Ellipsis,
+    /// Break it into more lines, between words where possible.
+    Wrap,
+}
+
+/// The kind of a [`Layout`] without its options, for overriding it from the
+/// command line.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutKind {
+    Rectangle,
+    Table,
+}
+
 impl Layout {
+    /// Switches to another kind of layout, keeping the other options.
+    pub fn with_kind(self, kind: LayoutKind) -> Self {
+        let (Layout::Rectangle {
+            round_corners,
+            border_style,
+            max_width,
+            overflow,
+        }
+        | Layout::Table {
+            round_corners,
+            border_style,
+            max_width,
+            overflow,
+        }) = self;
+        match kind {
+            LayoutKind::Rectangle => Layout::Rectangle {
+                round_corners,
+                border_style,
+                max_width,
+                overflow,
+            },
+            LayoutKind::Table => Layout::Table {
+                round_corners,
+                border_style,
+                max_width,
+                overflow,
+            },
+        }
+    }
+
     pub fn display(self, info: Vec<Info>, out: impl Write) -> Result<(), std::io::Error> {
         match self {
             Layout::Rectangle {
                 round_corners,
                 border_style,
-            } => display_rectangle(info, round_corners, border_style, out),
+                max_width,
+                overflow,
+            } => display_rectangle(info, round_corners, border_style, max_width, overflow, out),
             Layout::Table {
                 round_corners,
                 border_style,
-            } => display_table(info, round_corners, border_style, out),
+                max_width,
+                overflow,
+            } => display_table(info, round_corners, border_style, max_width, overflow, out),
+        }
+    }
+}
+
+/// A label and the lines of its value, or `None` for a separator. `overflow`
+/// is what to do if the lines are still too wide for the layout.
+type Row = Option<(Text, Vec<Text>, Overflow)>;
+
+/// Resolves every value on its own thread, so slow sources run concurrently.
+/// Lines are returned in config order, with `None` marking a separator, and
+/// each value is split into the lines its `max_width` allows. Entries whose
+/// `when` doesn't hold, or that failed with `on_error = "skip"`, are left out.
+fn resolve(info: Vec<Info>, overflow: Overflow) -> io::Result<Vec<Row>> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = info
+            .into_iter()
+            .map(|info| {
+                scope.spawn(move || -> io::Result<_> {
+                    if !info.when.holds() {
+                        return Ok(None);
+                    }
+                    let value: io::Result<Option<Text>> = info.value.try_into();
+                    let value = match value {
+                        Ok(value) => match &info.link {
+                            Some(link) => value.map(|value| value.with_link(link)),
+                            None => value,
+                        },
+                        Err(err) => match info.on_error.unwrap_or_default() {
+                            OnError::Abort => return Err(err),
+                            OnError::Skip => return Ok(None),
+                            OnError::Fallback => {
+                                Some(info.error_text.unwrap_or_else(default_error_text))
+                            }
+                        },
+                    };
+                    let overflow = info.overflow.unwrap_or(overflow);
+                    Ok(Some(value.map(|value| {
+                        let lines = match info.max_width {
+                            Some(width) => fit(value, width, overflow),
+                            None => vec![value],
+                        };
+                        (info.label, lines, overflow)
+                    })))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|handle| handle.join().expect("value thread panicked").transpose())
+            .collect()
+    })
+}
+
+/// Fits `text` into `width` columns as `overflow` says. Styles are kept, so
+/// each part of the text looks the same as before. Escape sequences in the
+/// text, like the colors of command output, take up no room and are never
+/// split, and colors and links they leave open are closed at the end of every
+/// line and reopened on the next.
+fn fit(text: Text, width: usize, overflow: Overflow) -> Vec<Text> {
+    if text.len() <= width {
+        return vec![text];
+    }
+    let mut pieces = Vec::new();
+    flatten(text, &mut pieces);
+    let clusters: Vec<Cluster> = pieces
+        .iter()
+        .enumerate()
+        .flat_map(|(i, piece)| {
+            let (Text::Styled { text, .. } | Text::Unstyled(text)) = piece else {
+                unreachable!("pieces are styled or unstyled");
+            };
+            clusters(text)
+                .into_iter()
+                .map(move |(text, width)| Cluster {
+                    text: text.to_string(),
+                    width,
+                    piece: i,
+                })
+        })
+        .collect();
+
+    // Where a line starting at `start` has to end to fit in `width` columns.
+    let end_of = |start: usize, width: usize| {
+        let mut used = 0;
+        clusters[start..]
+            .iter()
+            .take_while(|cluster| {
+                used += cluster.width;
+                used <= width
+            })
+            .count()
+            + start
+    };
+    let mut open = Open::default();
+    let mut line = |clusters: &[Cluster], ellipsis: bool| {
+        let (Some(first), Some(last)) = (clusters.first(), clusters.last()) else {
+            return Text::Empty;
+        };
+        let mut line = vec![Cluster::escape(open.reopen(), first.piece)];
+        line.extend_from_slice(clusters);
+        for cluster in clusters {
+            open.update(&cluster.text);
+        }
+        if ellipsis {
+            line.push(Cluster {
+                text: String::from("…"),
+                width: 1,
+                piece: last.piece,
+            });
+        }
+        line.push(Cluster::escape(open.close(), last.piece));
+        rebuild(&line, &pieces)
+    };
+
+    match overflow {
+        Overflow::Truncate => vec![line(&clusters[..end_of(0, width)], false)],
+        Overflow::Ellipsis => {
+            let Some(keep) = width.checked_sub(1) else {
+                return vec![Text::Empty];
+            };
+            let end = end_of(0, keep);
+            if end == 0 {
+                let ellipsis = Cluster {
+                    text: String::from("…"),
+                    width: 1,
+                    piece: 0,
+                };
+                return vec![rebuild(&[ellipsis], &pieces)];
+            }
+            vec![line(&clusters[..end], true)]
+        }
+        Overflow::Wrap => {
+            let mut lines = Vec::new();
+            let mut start = 0;
+            while start < clusters.len() {
+                // Every line gets at least one character, even if it is too wide.
+                let end = end_of(start, width).max(start + 1);
+                if end == clusters.len() {
+                    lines.push(line(&clusters[start..], false));
+                    break;
+                }
+                // Break at the last space that fits, or in the middle of a
+                // word that is too long for a line of its own.
+                match clusters[start + 1..=end]
+                    .iter()
+                    .rposition(|cluster| cluster.text.trim().is_empty())
+                {
+                    Some(space) => {
+                        lines.push(line(&clusters[start..start + 1 + space], false));
+                        start += space + 2;
+                    }
+                    None => {
+                        lines.push(line(&clusters[start..end], false));
+                        start = end;
+                    }
+                }
+            }
+            lines
+        }
+    }
+}
+
+/// A part of the text that [`fit`] doesn't split, with the piece it came from.
+#[derive(Clone)]
+struct Cluster {
+    text: String,
+    width: usize,
+    piece: usize,
+}
+
+impl Cluster {
+    fn escape(text: String, piece: usize) -> Self {
+        Self {
+            text,
+            width: 0,
+            piece,
+        }
+    }
+}
+
+/// The SGR and OSC 8 sequences that are still in effect at some point in the
+/// text.
+#[derive(Default)]
+struct Open {
+    sgr: Vec<String>,
+    link: Option<String>,
+}
+
+impl Open {
+    fn update(&mut self, escape: &str) {
+        if let Some(params) = escape
+            .strip_prefix("\x1b[")
+            .and_then(|sgr| sgr.strip_suffix('m'))
+        {
+            if params.is_empty() || params == "0" {
+                self.sgr.clear();
+            } else {
+                self.sgr.push(escape.to_string());
+            }
+        } else if let Some(link) = escape.strip_prefix("\x1b]8;") {
+            let url = link.split_once(';').map_or("", |(_, url)| url);
+            let url = url
+                .strip_suffix('\x07')
+                .or_else(|| url.strip_suffix("\x1b\\"))
+                .unwrap_or(url);
+            self.link = (!url.is_empty()).then(|| escape.to_string());
+        }
+    }
+
+    fn reopen(&self) -> String {
+        self.sgr
+            .iter()
+            .chain(&self.link)
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn close(&self) -> String {
+        let mut out = String::new();
+        if !self.sgr.is_empty() {
+            out.push_str("\x1b[0m");
+        }
+        if self.link.is_some() {
+            out.push_str("\x1b]8;;\x1b\\");
+        }
+        out
+    }
+}
+
+/// Collects the plain pieces of `text` in order, which are all `Styled` or
+/// `Unstyled`.
+fn flatten(text: Text, pieces: &mut Vec<Text>) {
+    match text {
+        Text::Styled { .. } | Text::Unstyled(_) => pieces.push(text),
+        Text::Combine(texts) => {
+            for text in texts {
+                flatten(text, pieces);
+            }
         }
+        Text::Empty => (),
     }
 }
 
+/// Puts clusters from [`fit`] back together, with the styles and links of the
+/// pieces they came from.
+fn rebuild(clusters: &[Cluster], pieces: &[Text]) -> Text {
+    let texts = clusters
+        .chunk_by(|a, b| a.piece == b.piece)
+        .map(|run| {
+            let text = run.iter().map(|cluster| cluster.text.as_str()).collect();
+            match &pieces[run[0].piece] {
+                Text::Styled { style, link, .. } => Text::Styled {
+                    text,
+                    style: *style,
+                    link: link.clone(),
+                },
+                _ => Text::Unstyled(text),
+            }
+        })
+        .collect();
+    Text::Combine(texts)
+}
+
 fn display_rectangle(
     info: Vec<Info>,
     round_corners: bool,
     border_style: Option<Style>,
+    max_width: Option<usize>,
+    overflow: Overflow,
     mut out: impl Write,
 ) -> io::Result<()> {
-    let lines: Vec<_> = info
+    let lines: Vec<_> = resolve(info, overflow)?
         .into_iter()
-        .map(|mut info| -> io::Result<_> {
-            let value: Option<Text> = info.value.try_into()?;
-            if let Some(value) = value {
-                info.label.append(value);
-                Ok(Some(info.label))
-            } else {
-                Ok(None)
-            }
+        .flat_map(|line| {
+            let Some((label, values, overflow)) = line else {
+                return vec![None];
+            };
+            // Lines after the first are lined up with the start of the value.
+            let indent = Text::Unstyled(" ".repeat(label.len()));
+            let mut label = Some(label);
+            values
+                .into_iter()
+                .flat_map(|value| {
+                    let mut line = label.take().unwrap_or_else(|| indent.clone());
+                    line.append(value);
+                    match max_width {
+                        Some(width) => fit(line, width.saturating_sub(4), overflow),
+                        None => vec![line],
+                    }
+                })
+                .map(Some)
+                .collect()
         })
-        .collect::<Result<_, _>>()?;
+        .collect();
     let max_len = lines
         .iter()
         .map(|l| l.as_ref().map_or(0, |l| l.len()))
         .max()
         .unwrap_or(0);
-    write!(
+    writeln!(
         out,
-        "{}{}{}{}\x1b[0m\n",
+        "{}{}{}{}{}",
         border_style.map_or_else(String::new, Style::format_start),
         if round_corners { "╭" } else { "┌" },
         "─".repeat(max_len + 2),
-        if round_corners { "╮" } else { "┐" }
+        if round_corners { "╮" } else { "┐" },
+        reset()
     )?;
     for line in lines {
         if let Some(line) = line {
             write!(out, "{} ", option_format(border_style, "│"))?;
             let len = line.len();
             let string: String = line.into();
-            out.write(string.as_bytes())?;
-            write!(
+            out.write_all(string.as_bytes())?;
+            writeln!(
                 out,
-                "{} {}\n",
+                "{} {}",
                 " ".repeat(max_len - len),
                 option_format(border_style, "│")
             )?;
         } else {
-            write!(
+            writeln!(
                 out,
-                "{}├{}┤\x1b[0m\n",
+                "{}├{}┤{}",
                 border_style.map_or_else(String::new, Style::format_start),
-                "─".repeat(max_len + 2)
+                "─".repeat(max_len + 2),
+                reset()
             )?;
         }
     }
-    write!(
+    writeln!(
         out,
-        "{}{}{}{}\x1b[0m\n",
+        "{}{}{}{}{}",
         border_style.map_or_else(String::new, Style::format_start),
         if round_corners { "╰" } else { "└" },
         "─".repeat(max_len + 2),
-        if round_corners { "╯" } else { "┘" }
+        if round_corners { "╯" } else { "┘" },
+        reset()
     )?;
     Ok(())
 }
@@ -102,44 +447,55 @@ fn display_table(
     info: Vec<Info>,
     round_corners: bool,
     border_style: Option<Style>,
+    max_width: Option<usize>,
+    overflow: Overflow,
     mut out: impl Write,
 ) -> io::Result<()> {
-    let lines: Vec<_> = info
-        .into_iter()
-        .map(|info| -> io::Result<_> {
-            let value: Option<Text> = info.value.try_into()?;
-            if let Some(value) = value {
-                Ok(Some((info.label, value)))
-            } else {
-                Ok(None)
-            }
-        })
-        .collect::<Result<_, _>>()?;
+    let lines = resolve(info, overflow)?;
     let max_len0 = lines
         .iter()
         .map(|l| l.as_ref().map_or(0, |l| l.0.len()))
         .max()
         .unwrap_or(0);
+    // Labels are never cut off, so values get what is left of `max_width`.
+    let lines: Vec<_> = lines
+        .into_iter()
+        .flat_map(|line| {
+            let Some((label, values, overflow)) = line else {
+                return vec![None];
+            };
+            let mut label = Some(label);
+            values
+                .into_iter()
+                .flat_map(|value| match max_width {
+                    Some(width) => fit(value, width.saturating_sub(max_len0 + 7), overflow),
+                    None => vec![value],
+                })
+                .map(|value| Some((label.take().unwrap_or_default(), value)))
+                .collect()
+        })
+        .collect();
     let max_len1 = lines
         .iter()
         .map(|l| l.as_ref().map_or(0, |l| l.1.len()))
         .max()
         .unwrap_or(0);
-    write!(
+    writeln!(
         out,
-        "{}{}{}┬{}{}\x1b[0m\n",
+        "{}{}{}┬{}{}{}",
         border_style.map_or_else(String::new, Style::format_start),
         if round_corners { "╭" } else { "┌" },
         "─".repeat(max_len0 + 2),
         "─".repeat(max_len1 + 2),
-        if round_corners { "╮" } else { "┐" }
+        if round_corners { "╮" } else { "┐" },
+        reset()
     )?;
     for line in lines {
         if let Some((label, value)) = line {
             write!(out, "{} ", option_format(border_style, "│"))?;
             let len0 = label.len();
             let label: String = label.into();
-            out.write(label.as_bytes())?;
+            out.write_all(label.as_bytes())?;
             write!(
                 out,
                 "{} {} ",
@@ -148,31 +504,151 @@ fn display_table(
             )?;
             let len1 = value.len();
             let value: String = value.into();
-            out.write(value.as_bytes())?;
-            write!(
+            out.write_all(value.as_bytes())?;
+            writeln!(
                 out,
-                "{} {}\n",
+                "{} {}",
                 " ".repeat(max_len1 - len1),
                 option_format(border_style, "│")
             )?;
         } else {
-            write!(
+            writeln!(
                 out,
-                "{}├{}┼{}┤\x1b[0m\n",
+                "{}├{}┼{}┤{}",
                 border_style.map_or_else(String::new, Style::format_start),
                 "─".repeat(max_len0 + 2),
-                "─".repeat(max_len1 + 2)
+                "─".repeat(max_len1 + 2),
+                reset()
             )?;
         }
     }
-    write!(
+    writeln!(
         out,
-        "{}{}{}┴{}{}\x1b[0m\n",
+        "{}{}{}┴{}{}{}",
         border_style.map_or_else(String::new, Style::format_start),
         if round_corners { "╰" } else { "└" },
         "─".repeat(max_len0 + 2),
         "─".repeat(max_len1 + 2),
-        if round_corners { "╯" } else { "┘" }
+        if round_corners { "╯" } else { "┘" },
+        reset()
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::style::Color;
+
+    fn lines(text: &str, width: usize, overflow: Overflow) -> Vec<String> {
+        fit(Text::Unstyled(text.to_string()), width, overflow)
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn leaves_text_that_fits() {
+        assert_eq!(lines("hello", 5, Overflow::Ellipsis), ["hello"]);
+    }
+
+    #[test]
+    fn truncates_and_ellipsizes() {
+        assert_eq!(lines("hello world", 5, Overflow::Truncate), ["hello"]);
+        assert_eq!(lines("hello world", 5, Overflow::Ellipsis), ["hell…"]);
+        assert_eq!(lines("日本語", 4, Overflow::Ellipsis), ["日…"]);
+    }
+
+    #[test]
+    fn ellipsis_in_tiny_widths() {
+        assert_eq!(lines("hello", 1, Overflow::Ellipsis), ["…"]);
+        assert_eq!(lines("hello", 0, Overflow::Ellipsis), [""]);
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(
+            lines("hello wide world", 10, Overflow::Wrap),
+            ["hello wide", "world"]
+        );
+        assert_eq!(lines("abcdefg", 3, Overflow::Wrap), ["abc", "def", "g"]);
+    }
+
+    #[test]
+    fn wraps_wide_characters() {
+        assert_eq!(
+            lines("日本語テキスト", 5, Overflow::Wrap),
+            ["日本", "語テ", "キス", "ト"]
+        );
+        // A character wider than the line still gets a line of its own.
+        assert_eq!(lines("日本", 1, Overflow::Wrap), ["日", "本"]);
+    }
+
+    #[test]
+    fn keeps_styles() {
+        let red = Style {
+            fg: Color::Red,
+            ..Default::default()
+        };
+        let text = Text::Combine(vec![
+            Text::Styled {
+                text: String::from("ab"),
+                style: red,
+                link: None,
+            },
+            Text::Unstyled(String::from("cd")),
+        ]);
+        let lines: Vec<String> = fit(text, 3, Overflow::Truncate)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines, [format!("{}c", red.format("ab"))]);
+    }
+
+    #[test]
+    fn skips_and_closes_escape_sequences() {
+        let text = "\x1b[31mredredred\x1b[0m tail";
+        assert_eq!(
+            lines(text, 6, Overflow::Ellipsis),
+            ["\x1b[31mredre…\x1b[0m"]
+        );
+        assert_eq!(
+            lines(text, 6, Overflow::Wrap),
+            ["\x1b[31mredred\x1b[0m", "\x1b[31mred\x1b[0m", "tail"]
+        );
+        assert_eq!(
+            lines("\x1b[1m\x1b[32mgreen text", 5, Overflow::Wrap),
+            ["\x1b[1m\x1b[32mgreen\x1b[0m", "\x1b[1m\x1b[32mtext\x1b[0m"]
+        );
+    }
+
+    #[test]
+    fn closes_links() {
+        let text = "\x1b]8;;https://example.com\x1b\\link text\x1b]8;;\x1b\\";
+        assert_eq!(
+            lines(text, 4, Overflow::Wrap),
+            [
+                "\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\",
+                "\x1b]8;;https://example.com\x1b\\text\x1b]8;;\x1b\\"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_emoji_sequences_together() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let flag = "🇳🇱";
+        assert_eq!(
+            lines(&format!("{family}{family}{family}"), 3, Overflow::Ellipsis),
+            [format!("{family}…")]
+        );
+        assert_eq!(
+            lines(&format!("{flag}{flag}{flag}"), 4, Overflow::Wrap),
+            [format!("{flag}{flag}"), flag.to_string()]
+        );
+        assert_eq!(
+            lines("e\u{301}e\u{301}e", 2, Overflow::Truncate),
+            ["e\u{301}e\u{301}"]
+        );
+    }
+}
@@ -1,77 +1,219 @@
-use std::{env, fmt::Display, fs, io::stdout, path::PathBuf, process, str};
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    io::{IsTerminal, stderr, stdout},
+    process, thread,
+};
 
-use config::{ArtPosition, Config};
+use art::{image, logos};
+use cli::Command;
+use config::{
+    ArtKind, ArtPosition, Config,
+    discovery::{self, ConfigSource},
+    include,
+    style::{self, Color, ColorChoice, Style},
+};
 
+mod art;
+mod cli;
 mod config;
 mod layout;
+mod terminal;
+mod width;
+
+const DEFAULT_CONFIG: &str = include_str!("default.toml");
 
 fn handle_error<T: Display, R>(message: T) -> R {
-    println!("\x1b[31;1merror\x1b[0m: {message}");
+    let label = if stderr().is_terminal() {
+        Style {
+            fg: Color::Red,
+            bold: true,
+            ..Default::default()
+        }
+        .format("error")
+    } else {
+        String::from("error")
+    };
+    eprintln!("{label}: {message}");
     process::exit(1)
 }
 
 fn main() {
-    let mut config_dir;
-    let mut args = env::args().skip(1);
-    let config_file = if let Some(path) = args.next() {
-        config_dir = PathBuf::from(&path);
+    // Errors from before the config is read are colored as `color = "auto"`
+    // would color them.
+    style::set_color_level(ColorChoice::Auto.level());
+    let args = match cli::parse(env::args().skip(1)).unwrap_or_else(handle_error) {
+        Command::Fetch(args) => args,
+        Command::Help => {
+            print!("{}", cli::HELP);
+            // Shows where the config comes from, so that a misplaced one
+            // doesn't go unnoticed.
+            match discovery::discover() {
+                Ok(ConfigSource::File(path)) => println!("\nConfig: {}", path.display()),
+                Ok(ConfigSource::Default { tried }) => {
+                    println!("\nConfig: built in, since none of these exist:");
+                    for path in tried {
+                        println!("  {}", path.display());
+                    }
+                }
+                Err(err) => println!("\nConfig: {err}"),
+            }
+            return;
+        }
+        Command::Version => {
+            println!("dyn-fetch {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Command::PrintDefaultConfig => {
+            print!("{DEFAULT_CONFIG}");
+            return;
+        }
+        Command::ListSources => {
+            for (name, description, fields) in cli::SOURCES {
+                if fields.is_empty() {
+                    println!("{name:<14}{description}");
+                } else {
+                    println!("{name:<14}{description} ({fields})");
+                }
+            }
+            return;
+        }
+    };
+    if args.no_color {
+        style::set_color_level(ColorChoice::Never.level());
+    }
+    let path = match args.config {
+        Some(path) => Some(path),
+        None => match discovery::discover().unwrap_or_else(handle_error) {
+            ConfigSource::File(path) => Some(path),
+            ConfigSource::Default { .. } => None,
+        },
+    };
+    let (config_dir, mut config) = if let Some(path) = path {
+        let mut config_dir = path.clone();
         config_dir.pop();
 
-        fs::read_to_string(path).unwrap_or_else(handle_error)
+        let config_file = fs::read_to_string(&path).unwrap_or_else(|err| {
+            handle_error(format!("Could not read `{}`: {err}", path.display()))
+        });
+        let config = include::parse(&config_file, &path).unwrap_or_else(handle_error);
+        (config_dir, config)
     } else {
-        config_dir = PathBuf::from(dirs::config_dir().unwrap());
-        config_dir.push("/dyn-fetch");
-
-        let mut config_file = config_dir.clone();
-        config_file.push("/config.toml");
-        fs::read_to_string(config_file).unwrap_or_else(handle_error)
+        let config_dir = dirs::config_dir().unwrap_or_default().join("dyn-fetch");
+        let config: Config = toml::from_str(DEFAULT_CONFIG).unwrap_or_else(handle_error);
+        (config_dir, config)
     };
-    let config: Config = toml::from_str(&config_file).unwrap_or_else(handle_error);
-    match config.art_layout {
-        ArtPosition::None => {
-            config
-                .layout
-                .display(config.info, stdout())
-                .unwrap_or_else(handle_error);
+    config.apply_defaults();
+    let color = if args.no_color {
+        ColorChoice::Never
+    } else {
+        config.color
+    };
+    style::set_color_level(color.level());
+    if let Some(width) = config.nerd_font_width {
+        width::set_private_use_width(width);
+    }
+    if let Some(art) = args.art {
+        // Unlike `art_path`, paths on the command line are relative to the
+        // current directory.
+        config.art_path = env::current_dir().unwrap_or_default().join(art);
+        config.art.builtin = None;
+        config.art_command = None;
+        if let ArtPosition::None = config.art_layout {
+            config.art_layout = ArtPosition::Left;
         }
-        _ => {
-            let mut file = config_dir.clone();
-            file.push(config.art_path);
-            let file = fs::read_to_string(file).unwrap_or_else(handle_error);
+    }
+    if let Some(art_layout) = args.art_layout {
+        config.art_layout = art_layout;
+    }
+    if let Some(kind) = args.layout {
+        config.layout = config.layout.with_kind(kind);
+    }
 
-            match config.art_layout {
-                ArtPosition::Top => {
-                    println!("{}", file);
-                    config
-                        .layout
-                        .display(config.info, stdout())
-                        .unwrap_or_else(handle_error);
-                }
-                ArtPosition::Left => {
-                    let mut buf = Vec::new();
-                    config
-                        .layout
-                        .display(config.info, &mut buf)
-                        .unwrap_or_else(handle_error);
-                    let art_size = file.lines().map(|l| l.len()).max().unwrap_or(0);
-                    let mut fetch_lines = buf.split(|c| *c == b'\n');
-                    let mut art_lines = file.lines();
-                    for (art, fetch) in art_lines.by_ref().zip(fetch_lines.by_ref()) {
-                        println!(
-                            "{art}{} {fetch}",
-                            " ".repeat(art_size - art.len()),
-                            fetch = str::from_utf8(fetch).unwrap()
-                        );
-                    }
-                    for line in art_lines {
-                        println!("{line}");
-                    }
-                    for line in fetch_lines {
-                        println!("{} {}", " ".repeat(art_size), str::from_utf8(line).unwrap());
-                    }
-                }
-                _ => unreachable!(),
+    // The art command runs while the info is resolved, since either can take a
+    // while.
+    let art_command = config
+        .art_command
+        .filter(|_| {
+            !matches!(config.art_layout, ArtPosition::None)
+                && matches!(config.art_kind, ArtKind::Text)
+                && config.art.builtin.is_none()
+        })
+        .map(|art_command| {
+            let timeout_ms = config.timeout_ms;
+            thread::spawn(move || art::run(&art_command, timeout_ms))
+        });
+    let mut buf = Vec::new();
+    config
+        .layout
+        .display(config.info, &mut buf)
+        .unwrap_or_else(handle_error);
+    let fetch = String::from_utf8(buf).unwrap_or_else(handle_error);
+
+    let mut colors = config.art.colors;
+    let mut overlay = None;
+    let art = match (
+        config.art_layout,
+        config.art_kind,
+        config.art.builtin,
+        art_command,
+    ) {
+        (ArtPosition::None, _, _, _) => String::new(),
+        (_, ArtKind::Image, _, _) => {
+            let path = art::pick(&config_dir.join(&config.art_path), config.art_pick)
+                .unwrap_or_else(handle_error);
+            let image = image::render(
+                &path,
+                config.art.width,
+                config.art.height,
+                config.art.protocol,
+            )
+            .unwrap_or_else(handle_error);
+            overlay = image.overlay;
+            image.text
+        }
+        (_, ArtKind::Text, Some(builtin), _) => {
+            let logo = logos::find(&builtin).unwrap_or_else(handle_error);
+            for (i, color) in logo.colors.iter().enumerate() {
+                colors.entry(format!("c{}", i + 1)).or_insert(Style {
+                    fg: *color,
+                    ..Default::default()
+                });
             }
+            art::render(logo.art, &colors).unwrap_or_else(handle_error)
         }
+        (_, ArtKind::Text, None, Some(art_command)) => {
+            let output = art_command
+                .join()
+                .expect("art command thread panicked")
+                .unwrap_or_else(handle_error);
+            art::render_output(&output, &colors).unwrap_or_else(handle_error)
+        }
+        (_, ArtKind::Text, None, None) => {
+            let path = art::pick(&config_dir.join(&config.art_path), config.art_pick)
+                .unwrap_or_else(handle_error);
+            let file = fs::read_to_string(path).unwrap_or_else(handle_error);
+            art::render(&file, &colors).unwrap_or_else(handle_error)
+        }
+    };
+    if let Some(columns) = terminal::columns() {
+        config.art_layout = art::fit(
+            config.art_layout,
+            &art,
+            &fetch,
+            columns,
+            config.art.top_below,
+            config.art.hide_below,
+        );
     }
+    art::compose(
+        &art,
+        overlay.as_deref(),
+        &fetch,
+        config.art_layout,
+        config.art_align,
+        stdout(),
+    )
+    .unwrap_or_else(handle_error);
 }
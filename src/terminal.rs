@@ -0,0 +1,22 @@
+use std::mem::MaybeUninit;
+
+/// The size of the terminal stdout is connected to, or `None` if it isn't
+/// connected to one.
+pub fn size() -> Option<libc::winsize> {
+    let mut size = MaybeUninit::<libc::winsize>::uninit();
+    // SAFETY: `TIOCGWINSZ` fills in a `winsize`, which is only read if the
+    // call succeeded.
+    unsafe {
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+            return None;
+        }
+        Some(size.assume_init())
+    }
+}
+
+/// The number of columns of the terminal, if it reports one.
+pub fn columns() -> Option<usize> {
+    size()
+        .map(|size| size.ws_col as usize)
+        .filter(|cols| *cols > 0)
+}
@@ -0,0 +1,119 @@
+use std::{
+    str::Chars,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+static PRIVATE_USE_WIDTH: AtomicUsize = AtomicUsize::new(1);
+
+/// Sets how many columns characters in the private use areas take up. Nerd
+/// Font icons live there, and depending on the font and terminal they are one
+/// or two columns wide.
+pub fn set_private_use_width(width: usize) {
+    PRIVATE_USE_WIDTH.store(width, Ordering::Relaxed);
+}
+
+fn is_private_use(c: char) -> bool {
+    matches!(
+        c,
+        '\u{e000}'..='\u{f8ff}' | '\u{f0000}'..='\u{ffffd}' | '\u{100000}'..='\u{10fffd}'
+    )
+}
+
+/// The number of terminal columns `c` takes up.
+pub fn char_width(c: char) -> usize {
+    if is_private_use(c) {
+        PRIVATE_USE_WIDTH.load(Ordering::Relaxed)
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+/// The number of terminal columns `text` takes up. Escape sequences are
+/// skipped, wide characters count as two columns, and combining marks and
+/// zero width joiners count as none.
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        width += plain_width(&rest[..start]);
+        let mut chars = rest[start + 1..].chars();
+        skip_escape(&mut chars);
+        rest = chars.as_str();
+    }
+    width + plain_width(rest)
+}
+
+/// Splits `text` into the parts that can't be broken up, with the number of
+/// columns each takes up: escape sequences, which take up none, and characters
+/// along with the zero width characters and emoji that are joined onto them.
+pub fn clusters(text: &str) -> Vec<(&str, usize)> {
+    let mut clusters: Vec<(&str, usize)> = Vec::new();
+    let mut chars = text.chars();
+    loop {
+        let start = text.len() - chars.as_str().len();
+        let Some(c) = chars.next() else {
+            break;
+        };
+        if c == '\x1b' {
+            skip_escape(&mut chars);
+            let end = text.len() - chars.as_str().len();
+            clusters.push((&text[start..end], 0));
+            continue;
+        }
+        let end = start + c.len_utf8();
+        match clusters.last_mut() {
+            // The second of a pair of regional indicators makes up a flag.
+            Some((last, _))
+                if !last.starts_with('\x1b')
+                    && (char_width(c) == 0
+                        || last.ends_with('\u{200d}')
+                        || is_regional_indicator(c)
+                            && last.chars().count() == 1
+                            && last.chars().all(is_regional_indicator)) =>
+            {
+                *last = &text[end - last.len() - c.len_utf8()..end];
+            }
+            _ => clusters.push((&text[start..end], 0)),
+        }
+    }
+    for (cluster, width) in &mut clusters {
+        if !cluster.starts_with('\x1b') {
+            *width = plain_width(cluster);
+        }
+    }
+    clusters
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1f1e6}'..='\u{1f1ff}')
+}
+
+/// The width of text without escape sequences. Whole strings are measured
+/// at once, so that emoji sequences count as a single character.
+fn plain_width(text: &str) -> usize {
+    let private_use = text.chars().filter(|c| is_private_use(*c)).count();
+    text.width().saturating_sub(private_use)
+        + private_use * PRIVATE_USE_WIDTH.load(Ordering::Relaxed)
+}
+
+/// Skips the rest of an escape sequence, after the `ESC`.
+fn skip_escape(chars: &mut Chars) {
+    match chars.next() {
+        // CSI sequences, like SGR, end with a byte in `@`..=`~`.
+        Some('[') => {
+            chars.by_ref().find(|c| ('@'..='~').contains(c));
+        }
+        // OSC sequences, like hyperlinks, end with `BEL` or `ESC \`, and so do
+        // the DCS and APC sequences used to draw images.
+        Some(']' | 'P' | '_') => {
+            while let Some(c) = chars.next() {
+                if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                    break;
+                }
+            }
+        }
+        _ => (),
+    }
+}